@@ -0,0 +1,190 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Shortest amount of time worth of bytes a throttled write waits to accumulate, to avoid waking
+/// up to write only a handful of bytes at a time.
+const MIN_WAIT: Duration = Duration::from_millis(10);
+
+/// A token bucket limiting the rate at which bytes are sent to a peer.
+///
+/// The bucket holds up to one second worth of bytes, which are replenished continuously at the
+/// configured rate. Without a rate, writes are never throttled but are still counted.
+#[derive(Debug)]
+pub(crate) struct BandwidthLimiter {
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    /// Bytes per second, or `None` if unlimited
+    rate: Option<u64>,
+    tokens: u64,
+    last_refill: Instant,
+    bytes_sent: u64,
+}
+
+impl BucketState {
+    fn refill(&mut self, rate: u64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let new_tokens = (elapsed.as_nanos() * rate as u128 / 1_000_000_000) as u64;
+        if self.tokens + new_tokens >= rate {
+            self.tokens = rate;
+            self.last_refill = now;
+        } else if new_tokens > 0 {
+            self.tokens += new_tokens;
+            // Only advance by the time it took to accumulate whole tokens so that fractional
+            // progress towards the next token isn't lost
+            self.last_refill +=
+                Duration::from_nanos((new_tokens as u128 * 1_000_000_000 / rate as u128) as u64);
+        }
+    }
+}
+
+impl BandwidthLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            state: Mutex::new(BucketState {
+                rate,
+                tokens: rate.unwrap_or(0),
+                last_refill: Instant::now(),
+                bytes_sent: 0,
+            }),
+        }
+    }
+
+    /// The configured rate in bytes per second, if any.
+    pub fn rate(&self) -> Option<u64> {
+        self.state.lock().unwrap().rate
+    }
+
+    /// Change the rate to `rate` bytes per second, or remove the limit if `None`. The bucket
+    /// starts out full at the new rate.
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = rate;
+        state.tokens = rate.unwrap_or(0);
+        state.last_refill = Instant::now();
+    }
+
+    /// Total number of bytes written through this limiter.
+    pub fn bytes_sent(&self) -> u64 {
+        self.state.lock().unwrap().bytes_sent
+    }
+
+    /// Take up to `len` bytes from the budget, returning the number of bytes which may be written.
+    ///
+    /// If the budget is exhausted, returns how long to wait before trying again instead.
+    fn acquire(&self, len: usize) -> Result<usize, Duration> {
+        self.acquire_at(len, Instant::now())
+    }
+
+    fn acquire_at(&self, len: usize, now: Instant) -> Result<usize, Duration> {
+        let mut state = self.state.lock().unwrap();
+        let rate = match state.rate {
+            Some(rate) => rate,
+            None => return Ok(len),
+        };
+
+        state.refill(rate, now);
+        if state.tokens == 0 && len > 0 {
+            let wanted = (len as u64).min(rate).max(1);
+            let wanted_time =
+                Duration::from_nanos((wanted as u128 * 1_000_000_000 / rate as u128).max(1) as u64);
+            return Err(wanted_time.max(MIN_WAIT).min(Duration::from_secs(1)));
+        }
+
+        let granted = (len as u64).min(state.tokens);
+        state.tokens -= granted;
+        Ok(granted as usize)
+    }
+
+    /// Record that `written` of the `granted` bytes were actually written, returning the rest to
+    /// the budget.
+    fn complete(&self, granted: usize, written: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.bytes_sent += written as u64;
+        if let Some(rate) = state.rate {
+            state.tokens = (state.tokens + (granted - written) as u64).min(rate);
+        }
+    }
+}
+
+/// Paces writes to a stream according to a shared [`BandwidthLimiter`].
+pub(crate) struct Throttle {
+    limiter: Arc<BandwidthLimiter>,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl Throttle {
+    pub fn new(limiter: Arc<BandwidthLimiter>) -> Self {
+        Self {
+            limiter,
+            sleep: None,
+        }
+    }
+
+    /// Wait until at least some of `len` bytes can be written, returning how many.
+    pub fn poll_acquire(&mut self, cx: &mut Context<'_>, len: usize) -> Poll<usize> {
+        loop {
+            if let Some(sleep) = &mut self.sleep {
+                futures::ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+
+            match self.limiter.acquire(len) {
+                Ok(granted) => return Poll::Ready(granted),
+                Err(wait) => self.sleep = Some(Box::pin(tokio::time::sleep(wait))),
+            }
+        }
+    }
+
+    /// See [`BandwidthLimiter::complete`].
+    pub fn complete(&self, granted: usize, written: usize) {
+        self.limiter.complete(granted, written)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BandwidthLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn unlimited() {
+        let limiter = BandwidthLimiter::new(None);
+        assert_eq!(limiter.acquire(1 << 20), Ok(1 << 20));
+        limiter.complete(1 << 20, 1 << 20);
+        assert_eq!(limiter.bytes_sent(), 1 << 20);
+    }
+
+    #[test]
+    fn budget_is_exhausted_and_refunded() {
+        let limiter = BandwidthLimiter::new(Some(1_000));
+        let now = Instant::now();
+
+        // The bucket starts out with one second worth of bytes
+        assert_eq!(limiter.acquire_at(600, now), Ok(600));
+        assert_eq!(limiter.acquire_at(600, now), Ok(400));
+        assert_eq!(
+            limiter.acquire_at(600, now),
+            Err(Duration::from_millis(600))
+        );
+
+        // Bytes which weren't written are returned to the budget
+        limiter.complete(400, 100);
+        assert_eq!(limiter.acquire_at(600, now), Ok(300));
+        assert_eq!(limiter.bytes_sent(), 100);
+
+        // and the budget is replenished over time
+        limiter.complete(300, 300);
+        assert_eq!(
+            limiter.acquire_at(600, now + Duration::from_millis(250)),
+            Ok(250)
+        );
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
-    crypto::{CertVerifier, ExpectedCertVerifier},
+    crypto::{CertVerifier, ExpectedCertVerifier, RecordingCertVerifier},
     PeerId, Result,
 };
 use pkcs8::EncodePrivateKey;
 use quinn::VarInt;
 use rcgen::{CertificateParams, KeyPair, SignatureAlgorithm};
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
 
 /// Configuration for a [`Network`](crate::Network).
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -17,11 +21,24 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub quic: Option<QuicConfig>,
 
+    /// Configuration for compressing request and response bodies.
+    ///
+    /// When set, bodies of outbound requests and responses which are at least
+    /// [`CompressionConfig::min_size`] bytes are compressed with zstd. Inbound bodies are always
+    /// decompressed when the sender indicates that they were compressed, regardless of this
+    /// setting.
+    ///
+    /// If unspecified, bodies will not be compressed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionConfig>,
+
     /// Size of the internal `ConnectionManager`s mailbox.
     ///
     /// One example of how this mailbox is used is for submitting
     /// connection requests via [`Network::connect`](crate::Network::connect).
     ///
+    /// Must be nonzero.
+    ///
     /// If unspecified, this will default to `128`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub connection_manager_channel_capacity: Option<usize>,
@@ -45,53 +62,233 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub connection_backoff_ms: Option<u64>,
 
+    /// Configuration for reconnecting to peers with
+    /// [`PeerAffinity::High`](crate::types::PeerAffinity::High) after a connection with them is
+    /// lost.
+    ///
+    /// Attempts to reconnect back off exponentially, rather than by
+    /// [`connection_backoff_ms`](Self::connection_backoff_ms), until a connection is established.
+    ///
+    /// If unspecified, the defaults described in [`ReconnectConfig`] are used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reconnect: Option<ReconnectConfig>,
+
     /// Set a timeout, in milliseconds, for all inbound and outbound connects.
     ///
     /// In unspecified, this will default to `10,000` milliseconds.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub connect_timeout_ms: Option<u64>,
 
+    /// Set a timeout, in milliseconds, for completing the QUIC and TLS handshake of inbound and
+    /// outbound connections, after which the connection attempt is aborted. This guards against
+    /// peers which hold a handshake half-open, separately from `connect-timeout-ms`, which also
+    /// covers the anemo handshake and any connect hook, and from
+    /// [`QuicConfig::max_idle_timeout_ms`], which governs established connections. Must be
+    /// nonzero.
+    ///
+    /// If unspecified, only `connect-timeout-ms` applies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake_timeout_ms: Option<u64>,
+
     /// Maximum number of concurrent connections to attempt to establish at a given point in time.
     ///
     /// If unspecified, this will default to `100`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_concurrent_outstanding_connecting_connections: Option<usize>,
 
+    /// Maximum number of new outbound connection attempts to start per second.
+    ///
+    /// Dials in excess of this rate are queued and started as the rate allows. This avoids
+    /// dialing a large number of peers all at once, e.g. when many peers are added to
+    /// [`KnownPeers`](crate::KnownPeers) at the same time. Connections requested explicitly via
+    /// [`Network::connect`](crate::Network::connect) bypass this limit unless
+    /// `rate-limit-explicit-connects` is set.
+    ///
+    /// Must be nonzero.
+    ///
+    /// If unspecified, outbound dials will not be rate limited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_outbound_dials_per_second: Option<u32>,
+
+    /// If true, connections requested explicitly via
+    /// [`Network::connect`](crate::Network::connect) are also subject to
+    /// `max-outbound-dials-per-second`.
+    ///
+    /// If unspecified, this will default to `false`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_explicit_connects: Option<bool>,
+
     /// Maximum number of concurrent connections to have established at a given point in time.
     ///
     /// This limit is applied in the following ways:
-    ///  - Inbound connections from [`KnownPeers`] with [`PeerAffinity::High`] bypass this limit. All
-    ///  other inbound connections are only accepted if the total number of inbound and outbound
-    ///  connections, irrespective of affinity, is less than this limit.
+    ///  - Inbound connections from [`KnownPeers`] with [`PeerAffinity::High`] bypass this limit,
+    ///    evicting a connected peer without [`PeerAffinity::High`], if there is one, to make room.
+    ///    Peers which aren't in [`KnownPeers`] or have [`PeerAffinity::Never`] are evicted first,
+    ///    followed by peers with [`PeerAffinity::Allowed`], picking the least recently used peer
+    ///    (the one with the oldest request in either direction) within each group. Peers with
+    ///    [`PeerAffinity::High`] are never evicted. All other inbound connections are only accepted
+    ///    if the total number of inbound and outbound connections, irrespective of affinity, is
+    ///    less than this limit and are otherwise refused.
     ///  - Outbound connections explicitly made by the application via [`Network::connect`] or
-    ///  [`Network::connect_with_peer_id`] bypass this limit.
-    ///  - Outbound connections made in the background, due to configured [`KnownPeers`], to peers with
-    ///  [`PeerAffinity::High`] bypass this limit and are always attempted, while peers with lower
-    ///  affinity respect this limit.
+    ///    [`Network::connect_with_peer_id`] bypass this limit.
+    ///  - Outbound connections made in the background, due to configured [`KnownPeers`], are only
+    ///    made to peers with [`PeerAffinity::High`] and bypass this limit.
+    ///
+    /// The limit can be changed while the network is running via [`Network::set_max_peers`].
     ///
     /// If unspecified, there will be no limit on the number of concurrent connections.
     ///
+    /// [`Network::set_max_peers`]: crate::Network::set_max_peers
     /// [`KnownPeers`]: crate::KnownPeers
     /// [`PeerAffinity::High`]: crate::types::PeerAffinity::High
+    /// [`PeerAffinity::Allowed`]: crate::types::PeerAffinity::Allowed
+    /// [`PeerAffinity::Never`]: crate::types::PeerAffinity::Never
     /// [`Network::connect`]: crate::Network::connect
     /// [`Network::connect_with_peer_id`]: crate::Network::connect_with_peer_id
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_concurrent_connections: Option<usize>,
 
+    /// Maximum number of inbound connections to accept from a single IP address.
+    ///
+    /// Inbound connections from an IP address which already has this many inbound connections
+    /// established are refused, limiting the number of connection slots a single host can take up.
+    /// Inbound connections from [`KnownPeers`] with [`PeerAffinity::High`] bypass this limit and
+    /// don't count towards it, as do all outbound connections. Must be nonzero.
+    ///
+    /// If unspecified, there will be no limit on the number of connections per IP address.
+    ///
+    /// [`KnownPeers`]: crate::KnownPeers
+    /// [`PeerAffinity::High`]: crate::types::PeerAffinity::High
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections_per_ip: Option<usize>,
+
     /// Size of the broadcast channel use for subscribing to
     /// [`PeerEvent`](crate::types::PeerEvent)s via
     /// [`Network::subscribe`](crate::Network::subscribe).
     ///
+    /// Must be nonzero.
+    ///
     /// If unspecified, this will default to `128`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub peer_event_broadcast_channel_capacity: Option<usize>,
 
-    /// Set the maximum frame size in bytes.
+    /// Size of the channel returned by [`Network::datagrams`](crate::Network::datagrams) used to
+    /// deliver inbound datagrams.
+    ///
+    /// Datagrams are dropped, according to `datagram_overflow_policy`, if the channel is full.
+    /// Must be nonzero.
+    ///
+    /// If unspecified, this will default to `128`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datagram_channel_capacity: Option<usize>,
+
+    /// Which datagram to drop when a datagram arrives for a receiver returned by
+    /// [`Network::datagrams`](crate::Network::datagrams) whose channel is full. Dropped datagrams
+    /// are counted in [`EndpointStats::datagrams_dropped`](crate::types::EndpointStats::datagrams_dropped).
+    ///
+    /// If unspecified, this will default to [`DatagramOverflowPolicy::DropNewest`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datagram_overflow_policy: Option<DatagramOverflowPolicy>,
+
+    /// Size of the channel returned by
+    /// [`Network::accept_raw_streams`](crate::Network::accept_raw_streams) used to deliver inbound
+    /// raw streams.
+    ///
+    /// Streams are reset if no channel has room for them. Must be nonzero.
+    ///
+    /// If unspecified, this will default to `128`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_stream_channel_capacity: Option<usize>,
+
+    /// Size of the channel returned by
+    /// [`Network::incoming_pushes`](crate::Network::incoming_pushes) used to deliver messages
+    /// pushed by peers.
     ///
-    /// This controls the maximum size of a request or response.
+    /// Once the channel is full, delivery of further pushes waits until there is room. Must be
+    /// nonzero.
+    ///
+    /// If unspecified, this will default to `128`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push_channel_capacity: Option<usize>,
+
+    /// Maximum number of inbound requests from a single peer which will be processed
+    /// concurrently.
+    ///
+    /// Requests beyond this limit are queued, up to `max-queued-requests-per-peer`, until an
+    /// in-flight request from the same peer completes. Must be nonzero.
+    ///
+    /// If unspecified, this will default to `100`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests_per_peer: Option<usize>,
+
+    /// Maximum number of streams which can be open to a single peer at once, across RPCs,
+    /// messages, streaming RPCs and raw streams.
+    ///
+    /// Attempts to open a stream beyond this limit fail immediately with
+    /// [`RpcError::TooManyStreams`](crate::RpcError::TooManyStreams) rather than waiting for
+    /// the peer to allow more streams. The number of currently open streams is available via
+    /// [`Peer::outbound_streams`](crate::Peer::outbound_streams), which can help with tuning
+    /// this along with [`QuicConfig::max_concurrent_bidi_streams`]. Must be nonzero.
+    ///
+    /// If unspecified, there will be no limit and streams beyond the peer's QUIC stream limit
+    /// wait for an existing stream to close.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_outbound_streams_per_peer: Option<usize>,
+
+    /// Maximum number of bytes per second which will be written to the streams sent to a single
+    /// peer, including responses to its requests. Writes beyond the limit wait for the budget to be
+    /// replenished rather than failing, and up to one second worth of bytes can be sent in a
+    /// burst. The limit can be changed for individual peers via
+    /// [`Peer::set_outbound_bandwidth_limit`](crate::Peer::set_outbound_bandwidth_limit). Must be
+    /// nonzero.
     ///
     /// If unspecified, there will be no limit.
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_outbound_bytes_per_second_per_peer: Option<u64>,
+
+    /// Maximum number of inbound requests from a single peer which will be queued waiting to be
+    /// processed once `max-concurrent-requests-per-peer` has been reached.
+    ///
+    /// Once the queue is full no new streams are accepted from the peer until one of its requests
+    /// completes. Streams which haven't been accepted are left to QUIC's flow control, which
+    /// prevents the peer from opening more than `max-concurrent-bidi-streams` (or
+    /// `max-concurrent-uni-streams`) streams at once, so memory use is bounded even when the
+    /// service is unable to keep up.
+    ///
+    /// If unspecified, this will default to `1,000`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_queued_requests_per_peer: Option<usize>,
+
+    /// Maximum number of inbound requests, across all peers, which will be processed
+    /// concurrently.
+    ///
+    /// Requests beyond this limit wait until an in-flight request completes, in the order
+    /// determined by `request-scheduling`. This is applied in addition to
+    /// `max-concurrent-requests-per-peer`. Must be nonzero.
+    ///
+    /// If unspecified, there will be no limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<usize>,
+
+    /// How inbound requests waiting for one of the `max-concurrent-requests` slots are
+    /// scheduled.
+    ///
+    /// Has no effect unless `max-concurrent-requests` is set.
+    ///
+    /// If unspecified, this will default to [`RequestScheduling::Fifo`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_scheduling: Option<RequestScheduling>,
+
+    /// Set the maximum frame size in bytes.
+    ///
+    /// This controls the maximum size of a request or response, in both the inbound and outbound
+    /// direction. Inbound frames which declare a length larger than this are rejected before any
+    /// memory is allocated for them and the stream they arrived on is reset.
+    ///
+    /// Must be nonzero.
+    ///
+    /// If unspecified, this will default to 8MiB.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_frame_size: Option<usize>,
 
     /// Set a timeout, in milliseconds, for all inbound requests.
@@ -113,12 +310,136 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub outbound_request_timeout_ms: Option<u64>,
 
+    /// Set a timeout, in milliseconds, for how long an inbound stream can go without receiving
+    /// any data while its request is being read.
+    ///
+    /// Streams which stall for longer than this, e.g. due to a peer sending a partial request and
+    /// then going silent, are reset so that the resources used to handle them are reclaimed. The
+    /// connection itself is unaffected. Must be nonzero.
+    ///
+    /// If unspecified, this will default to `30,000` milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_idle_timeout_ms: Option<u64>,
+
     /// Set a timeout, in milliseconds, until the peers are notified when network
     /// is shutting down
     ///
     /// If unspecified, then this will default to 1 minute (60 * 1_000 ms)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub shutdown_idle_timeout_ms: Option<u64>,
+
+    /// If true, TLS sessions with peers are retained so that subsequent outbound connections to
+    /// the same peer resume the previous session, and peers are allowed to resume their sessions
+    /// when connecting to us. Whether a connection resumed a session is reported by
+    /// [`ConnectionStats::resumed`](crate::types::ConnectionStats::resumed).
+    ///
+    /// Resumed connections still complete the full handshake before they're handed to the
+    /// application. 0-RTT early data is neither sent nor accepted, as it isn't protected against
+    /// replay attacks and sending it would require handing out a connection before the peer has
+    /// accepted it.
+    ///
+    /// If unspecified, this will default to `false`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_session_resumption: Option<bool>,
+
+    /// If true, the source address of every inbound connection attempt is validated by sending a
+    /// QUIC Retry packet before any handshake state is created or any response larger than the
+    /// attempt itself is sent.
+    ///
+    /// This prevents the network from being used to amplify traffic towards a spoofed address and
+    /// makes it costlier to exhaust resources with spoofed connection attempts, at the cost of an
+    /// extra round trip when establishing every inbound connection. Attempts which don't echo the
+    /// retry token back are dropped by the QUIC implementation without being reported.
+    ///
+    /// If unspecified, this will default to `false`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_address_validation: Option<bool>,
+
+    /// If set, only peers in this set are able to connect with us and we only connect with peers
+    /// in this set.
+    ///
+    /// This is enforced while verifying the peer's certificate during the TLS handshake, for both
+    /// inbound and outbound connections, so a connection with any other peer is never
+    /// established. Outbound connections refused for this reason fail with an error which can be
+    /// downcast to a [`PeerNotAllowed`](crate::PeerNotAllowed).
+    ///
+    /// If unspecified, connections with any peer are allowed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_peers: Option<HashSet<PeerId>>,
+
+    /// Identifier of the logical network this node belongs to, e.g. a chain id.
+    ///
+    /// When set, the id is exchanged during the handshake and connections with peers which don't
+    /// present the same id are refused, with [`DisconnectReason::NetworkMismatch`] reported to the
+    /// peer. This prevents nodes of different networks from peering by accident, even when they
+    /// share the same TLS configuration.
+    ///
+    /// If unspecified, no id is presented and peers of any network are accepted, although peers
+    /// which have an id configured will refuse the connection.
+    ///
+    /// [`DisconnectReason::NetworkMismatch`]: crate::types::DisconnectReason::NetworkMismatch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_id: Option<[u8; 32]>,
+
+    /// Override the wire protocol version advertised during the handshake, in order to simulate
+    /// a peer running an incompatible version.
+    #[cfg(test)]
+    #[serde(skip)]
+    pub(crate) protocol_version: Option<u16>,
+
+    /// Perform the handshake the way peers predating version negotiation do, in order to test
+    /// compatibility with them.
+    #[cfg(test)]
+    #[serde(skip)]
+    pub(crate) legacy_handshake: bool,
+}
+
+/// Configuration for zstd compression of request and response bodies.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct CompressionConfig {
+    /// Minimum size, in bytes, of a body before it is compressed.
+    ///
+    /// If unspecified, this will default to `1024`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<usize>,
+
+    /// The zstd compression level to use.
+    ///
+    /// If unspecified, zstd's default compression level will be used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<i32>,
+}
+
+/// Configuration for reconnecting to peers with
+/// [`PeerAffinity::High`](crate::types::PeerAffinity::High), see
+/// [`Config::reconnect`](Config#structfield.reconnect).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct ReconnectConfig {
+    /// Delay, in milliseconds, before the second attempt to reconnect with a peer. The delay
+    /// doubles after each further failed attempt. Must be nonzero.
+    ///
+    /// If unspecified, this will default to `100` milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_delay_ms: Option<u64>,
+
+    /// Maximum delay, in milliseconds, between 2 consecutive attempts to reconnect with a peer,
+    /// before jitter is added.
+    ///
+    /// If unspecified, this will default to `60,000` milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_delay_ms: Option<u64>,
+
+    /// Fraction of each delay, in the range `[0.0, 1.0]`, to randomly add to it in order to avoid
+    /// many nodes reconnecting in lockstep. Jitter is added after the delay has been capped at
+    /// [`max_delay_ms`](Self::max_delay_ms), so a delay can exceed it by up to this fraction.
+    ///
+    /// If unspecified, this will default to `0.1`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<f64>,
 }
 
 /// Configuration for the underlying QUIC transport.
@@ -145,6 +466,10 @@ pub struct QuicConfig {
     /// Maximum number of bytes a peer may transmit without acknowledgement on any one stream
     /// before becoming blocked.
     ///
+    /// Raising this allows a single stream to make better use of links with a high
+    /// bandwidth-delay product, at the cost of buffering up to this many bytes per open stream.
+    /// Must be nonzero.
+    ///
     /// If unspecified, this will default to 1.25MB.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream_receive_window: Option<u64>,
@@ -152,12 +477,19 @@ pub struct QuicConfig {
     /// Maximum number of bytes a peer may transmit across all streams of a connection before
     /// becoming blocked.
     ///
+    /// This bounds the memory a single connection can use for buffering received data, which
+    /// would otherwise only be limited by `stream_receive_window` multiplied by
+    /// `max_concurrent_bidi_streams`. Must be nonzero.
+    ///
     /// If unspecified, this will default to unlimited.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub receive_window: Option<u64>,
 
     /// Maximum number of bytes to transmit to a peer without acknowledgment
     ///
+    /// Up to this many bytes of sent data may be buffered per connection until acknowledged, so
+    /// it should be at least the bandwidth-delay product of the link. Must be nonzero.
+    ///
     /// If unspecified, this will default to 10MB.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub send_window: Option<u64>,
@@ -183,10 +515,29 @@ pub struct QuicConfig {
     ///
     /// Keep-alives prevent otherwise idle connections from timing out.
     ///
+    /// Must be less than `max_idle_timeout_ms`, otherwise connections would time out before a
+    /// keep-alive is sent.
+    ///
     /// If unspecified, this will default to `None`, disabling keep-alives.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub keep_alive_interval_ms: Option<u64>,
 
+    /// Round-trip time to assume for a new connection until it has been measured.
+    ///
+    /// This governs retransmission timers during the handshake, so on high-latency links, e.g.
+    /// satellite or intercontinental ones, raising it avoids spuriously retransmitting packets
+    /// before the peer has had a chance to respond. Must be nonzero.
+    ///
+    /// If unspecified, this will default to `333` milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_rtt_ms: Option<u64>,
+
+    /// Congestion control algorithm to use for connections.
+    ///
+    /// If unspecified, this will default to [`CongestionController::Cubic`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub congestion_controller: Option<CongestionController>,
+
     /// Size of the send buffer on the UDP socket (`SO_SNDBUF`).
     ///
     /// If unspecified, this will use the operating system default.
@@ -205,7 +556,129 @@ pub struct QuicConfig {
     pub allow_failed_socket_buffer_size_setting: bool,
 }
 
+/// Congestion control algorithms which can be used by the QUIC transport.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CongestionController {
+    /// The CUBIC algorithm, as described in [RFC 8312](https://www.rfc-editor.org/rfc/rfc8312).
+    #[default]
+    Cubic,
+    /// The NewReno algorithm, as described in [RFC 6582](https://www.rfc-editor.org/rfc/rfc6582).
+    NewReno,
+    /// An experimental implementation of BBR, which can perform significantly better than
+    /// loss-based algorithms on links with a high bandwidth-delay product.
+    Bbr,
+}
+
+/// Policies for choosing which datagram to drop when a receiver's channel is full, see
+/// [`Config::datagram_overflow_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatagramOverflowPolicy {
+    /// The newly arrived datagram is dropped, keeping the datagrams already buffered.
+    #[default]
+    DropNewest,
+    /// The oldest buffered datagram is dropped to make room, favoring the most recent datagrams.
+    DropOldest,
+}
+
+/// Policies for scheduling inbound requests once
+/// [`Config::max_concurrent_requests`] has been reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequestScheduling {
+    /// Requests are processed in the order they arrived, regardless of which peer sent them.
+    #[default]
+    Fifo,
+    /// Requests are processed round-robin across connections, so that a peer with many pending
+    /// requests can't starve a peer with only a few.
+    Fair,
+}
+
 impl Config {
+    /// Check that this config is valid, returning an error describing the first invalid setting.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.connection_manager_channel_capacity() == 0 {
+            return Err(anyhow::anyhow!(
+                "connection-manager-channel-capacity must be nonzero"
+            ));
+        }
+
+        if self.peer_event_broadcast_channel_capacity() == 0 {
+            return Err(anyhow::anyhow!(
+                "peer-event-broadcast-channel-capacity must be nonzero"
+            ));
+        }
+
+        if self.max_concurrent_requests_per_peer() == 0 {
+            return Err(anyhow::anyhow!(
+                "max-concurrent-requests-per-peer must be nonzero"
+            ));
+        }
+
+        if self.max_concurrent_requests == Some(0) {
+            return Err(anyhow::anyhow!("max-concurrent-requests must be nonzero"));
+        }
+
+        if self.stream_idle_timeout_ms == Some(0) {
+            return Err(anyhow::anyhow!("stream-idle-timeout-ms must be nonzero"));
+        }
+
+        if self.handshake_timeout_ms == Some(0) {
+            return Err(anyhow::anyhow!("handshake-timeout-ms must be nonzero"));
+        }
+
+        if self.max_connections_per_ip == Some(0) {
+            return Err(anyhow::anyhow!("max-connections-per-ip must be nonzero"));
+        }
+
+        if self.max_concurrent_outbound_streams_per_peer == Some(0) {
+            return Err(anyhow::anyhow!(
+                "max-concurrent-outbound-streams-per-peer must be nonzero"
+            ));
+        }
+
+        if self.max_outbound_bytes_per_second_per_peer == Some(0) {
+            return Err(anyhow::anyhow!(
+                "max-outbound-bytes-per-second-per-peer must be nonzero"
+            ));
+        }
+
+        if self.max_outbound_dials_per_second == Some(0) {
+            return Err(anyhow::anyhow!(
+                "max-outbound-dials-per-second must be nonzero"
+            ));
+        }
+
+        if self.max_frame_size() == 0 {
+            return Err(anyhow::anyhow!("max-frame-size must be nonzero"));
+        }
+
+        if self.datagram_channel_capacity() == 0 {
+            return Err(anyhow::anyhow!("datagram-channel-capacity must be nonzero"));
+        }
+
+        if self.raw_stream_channel_capacity() == 0 {
+            return Err(anyhow::anyhow!(
+                "raw-stream-channel-capacity must be nonzero"
+            ));
+        }
+
+        if self.push_channel_capacity() == 0 {
+            return Err(anyhow::anyhow!("push-channel-capacity must be nonzero"));
+        }
+
+        if let Some(reconnect) = &self.reconnect {
+            reconnect.validate()?;
+        }
+
+        if let Some(quic) = &self.quic {
+            quic.validate()?;
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn transport_config(&self) -> quinn::TransportConfig {
         self.quic
             .as_ref()
@@ -244,6 +717,14 @@ impl Config {
         Duration::from_millis(self.connection_backoff_ms.unwrap_or(CONNECTION_BACKOFF_MS))
     }
 
+    pub(crate) fn reconnect(&self) -> ReconnectConfig {
+        self.reconnect.clone().unwrap_or_default()
+    }
+
+    pub(crate) fn handshake_timeout(&self) -> Option<Duration> {
+        self.handshake_timeout_ms.map(Duration::from_millis)
+    }
+
     pub(crate) fn connect_timeout(&self) -> Duration {
         const CONNECTION_TIMEOUT_MS: u64 = 10_000; // 10 seconds
 
@@ -257,10 +738,26 @@ impl Config {
             .unwrap_or(MAX_CONCURRENT_OUTSTANDING_CONNECTING_CONNECTIONS)
     }
 
+    /// The minimum amount of time between starting rate limited outbound dials, or `None` if
+    /// dials aren't rate limited.
+    pub(crate) fn outbound_dial_interval(&self) -> Option<Duration> {
+        self.max_outbound_dials_per_second
+            .filter(|rate| *rate > 0)
+            .map(|rate| Duration::from_secs(1) / rate)
+    }
+
+    pub(crate) fn rate_limit_explicit_connects(&self) -> bool {
+        self.rate_limit_explicit_connects.unwrap_or(false)
+    }
+
     pub(crate) fn max_concurrent_connections(&self) -> Option<usize> {
         self.max_concurrent_connections
     }
 
+    pub(crate) fn max_connections_per_ip(&self) -> Option<usize> {
+        self.max_connections_per_ip
+    }
+
     pub(crate) fn peer_event_broadcast_channel_capacity(&self) -> usize {
         const PEER_EVENT_BROADCAST_CHANNEL_CAPACITY: usize = 128;
 
@@ -268,8 +765,78 @@ impl Config {
             .unwrap_or(PEER_EVENT_BROADCAST_CHANNEL_CAPACITY)
     }
 
-    pub(crate) fn max_frame_size(&self) -> Option<usize> {
-        self.max_frame_size
+    pub(crate) fn datagram_channel_capacity(&self) -> usize {
+        const DATAGRAM_CHANNEL_CAPACITY: usize = 128;
+
+        self.datagram_channel_capacity
+            .unwrap_or(DATAGRAM_CHANNEL_CAPACITY)
+    }
+
+    pub(crate) fn datagram_overflow_policy(&self) -> DatagramOverflowPolicy {
+        self.datagram_overflow_policy.unwrap_or_default()
+    }
+
+    pub(crate) fn raw_stream_channel_capacity(&self) -> usize {
+        const RAW_STREAM_CHANNEL_CAPACITY: usize = 128;
+
+        self.raw_stream_channel_capacity
+            .unwrap_or(RAW_STREAM_CHANNEL_CAPACITY)
+    }
+
+    pub(crate) fn push_channel_capacity(&self) -> usize {
+        const PUSH_CHANNEL_CAPACITY: usize = 128;
+
+        self.push_channel_capacity.unwrap_or(PUSH_CHANNEL_CAPACITY)
+    }
+
+    /// The highest wire protocol version supported by this node.
+    pub(crate) fn protocol_version(&self) -> u16 {
+        #[cfg(test)]
+        if let Some(version) = self.protocol_version {
+            return version;
+        }
+
+        crate::types::Version::LATEST.to_u16()
+    }
+
+    pub(crate) fn compression(&self) -> Option<&CompressionConfig> {
+        self.compression.as_ref()
+    }
+
+    pub(crate) fn max_concurrent_requests_per_peer(&self) -> usize {
+        const MAX_CONCURRENT_REQUESTS_PER_PEER: usize = 100;
+
+        self.max_concurrent_requests_per_peer
+            .unwrap_or(MAX_CONCURRENT_REQUESTS_PER_PEER)
+    }
+
+    pub(crate) fn max_concurrent_outbound_streams_per_peer(&self) -> Option<usize> {
+        self.max_concurrent_outbound_streams_per_peer
+    }
+
+    pub(crate) fn max_outbound_bytes_per_second_per_peer(&self) -> Option<u64> {
+        self.max_outbound_bytes_per_second_per_peer
+    }
+
+    pub(crate) fn max_queued_requests_per_peer(&self) -> usize {
+        const MAX_QUEUED_REQUESTS_PER_PEER: usize = 1_000;
+
+        self.max_queued_requests_per_peer
+            .unwrap_or(MAX_QUEUED_REQUESTS_PER_PEER)
+    }
+
+    pub(crate) fn max_concurrent_requests(&self) -> Option<usize> {
+        self.max_concurrent_requests
+    }
+
+    pub(crate) fn request_scheduling(&self) -> RequestScheduling {
+        self.request_scheduling.unwrap_or_default()
+    }
+
+    pub(crate) fn max_frame_size(&self) -> usize {
+        const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024; // 8MiB
+
+        self.max_frame_size.unwrap_or(DEFAULT_MAX_FRAME_SIZE)
     }
 
     pub(crate) fn inbound_request_timeout(&self) -> Option<Duration> {
@@ -280,6 +847,31 @@ impl Config {
         self.outbound_request_timeout_ms.map(Duration::from_millis)
     }
 
+    pub(crate) fn stream_idle_timeout(&self) -> Duration {
+        const DEFAULT_STREAM_IDLE_TIMEOUT_MS: u64 = 30_000;
+
+        Duration::from_millis(
+            self.stream_idle_timeout_ms
+                .unwrap_or(DEFAULT_STREAM_IDLE_TIMEOUT_MS),
+        )
+    }
+
+    pub(crate) fn enable_session_resumption(&self) -> bool {
+        self.enable_session_resumption.unwrap_or(false)
+    }
+
+    pub(crate) fn require_address_validation(&self) -> bool {
+        self.require_address_validation.unwrap_or(false)
+    }
+
+    pub(crate) fn allowed_peers(&self) -> Option<&HashSet<PeerId>> {
+        self.allowed_peers.as_ref()
+    }
+
+    pub(crate) fn network_id(&self) -> Option<&[u8; 32]> {
+        self.network_id.as_ref()
+    }
+
     pub(crate) fn shutdown_idle_timeout(&self) -> Duration {
         const DEFAULT_SHUTDOWN_IDLE_TIMEOUT_MS: u64 = 60_000; // 1 minute
 
@@ -289,7 +881,111 @@ impl Config {
     }
 }
 
+impl CompressionConfig {
+    pub(crate) fn min_size(&self) -> usize {
+        const DEFAULT_MIN_SIZE: usize = 1024;
+
+        self.min_size.unwrap_or(DEFAULT_MIN_SIZE)
+    }
+
+    pub(crate) fn level(&self) -> i32 {
+        self.level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+}
+
+impl CongestionController {
+    /// Configure `transport` to use this congestion control algorithm.
+    pub(crate) fn apply(self, transport: &mut quinn::TransportConfig) {
+        use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
+
+        match self {
+            CongestionController::Cubic => {
+                transport.congestion_controller_factory(Arc::new(CubicConfig::default()))
+            }
+            CongestionController::NewReno => {
+                transport.congestion_controller_factory(Arc::new(NewRenoConfig::default()))
+            }
+            CongestionController::Bbr => {
+                transport.congestion_controller_factory(Arc::new(BbrConfig::default()))
+            }
+        };
+    }
+}
+
+impl ReconnectConfig {
+    fn validate(&self) -> Result<()> {
+        if self.base_delay_ms == Some(0) {
+            return Err(anyhow::anyhow!("reconnect.base-delay-ms must be nonzero"));
+        }
+
+        if let Some(jitter) = self.jitter {
+            if !(0.0..=1.0).contains(&jitter) {
+                return Err(anyhow::anyhow!(
+                    "reconnect.jitter must be between 0.0 and 1.0, got {jitter}"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn base_delay(&self) -> Duration {
+        const BASE_DELAY_MS: u64 = 100;
+
+        Duration::from_millis(self.base_delay_ms.unwrap_or(BASE_DELAY_MS))
+    }
+
+    pub(crate) fn max_delay(&self) -> Duration {
+        const MAX_DELAY_MS: u64 = 60_000; // 1 minute
+
+        Duration::from_millis(self.max_delay_ms.unwrap_or(MAX_DELAY_MS))
+    }
+
+    pub(crate) fn jitter(&self) -> f64 {
+        const JITTER: f64 = 0.1;
+
+        self.jitter.unwrap_or(JITTER)
+    }
+}
+
 impl QuicConfig {
+    fn validate(&self) -> Result<()> {
+        // Matches quinn's default idle timeout
+        const DEFAULT_MAX_IDLE_TIMEOUT_MS: u64 = 10_000;
+
+        if self.stream_receive_window == Some(0) {
+            return Err(anyhow::anyhow!("stream-receive-window must be nonzero"));
+        }
+
+        if self.receive_window == Some(0) {
+            return Err(anyhow::anyhow!("receive-window must be nonzero"));
+        }
+
+        if self.send_window == Some(0) {
+            return Err(anyhow::anyhow!("send-window must be nonzero"));
+        }
+
+        if self.initial_rtt_ms == Some(0) {
+            return Err(anyhow::anyhow!("initial-rtt-ms must be nonzero"));
+        }
+
+        let max_idle_timeout_ms = self
+            .max_idle_timeout_ms
+            .unwrap_or(DEFAULT_MAX_IDLE_TIMEOUT_MS);
+
+        // An idle timeout of 0 disables the timeout entirely
+        if let Some(keep_alive_interval_ms) = self.keep_alive_interval_ms {
+            if max_idle_timeout_ms != 0 && keep_alive_interval_ms >= max_idle_timeout_ms {
+                return Err(anyhow::anyhow!(
+                    "keep-alive-interval-ms ({keep_alive_interval_ms}) must be less than \
+                     max-idle-timeout-ms ({max_idle_timeout_ms})"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn transport_config(&self) -> quinn::TransportConfig {
         let mut config = quinn::TransportConfig::default();
 
@@ -341,6 +1037,10 @@ impl QuicConfig {
             config.keep_alive_interval(Some(keep_alive_interval));
         }
 
+        if let Some(initial_rtt) = self.initial_rtt_ms.map(Duration::from_millis) {
+            config.initial_rtt(initial_rtt);
+        }
+
         config
     }
 }
@@ -360,7 +1060,21 @@ pub(crate) struct EndpointConfigBuilder {
     /// initiating outbound connections.
     pub alternate_server_name: Option<String>,
 
+    /// ALPN protocols to offer and accept, in order of preference.
+    pub alpn_protocols: Vec<Vec<u8>>,
+
     pub transport_config: Option<quinn::TransportConfig>,
+
+    pub congestion_controller: Option<CongestionController>,
+
+    pub enable_session_resumption: bool,
+
+    pub require_address_validation: bool,
+
+    pub allowed_peers: Option<HashSet<PeerId>>,
+
+    /// How long to wait for a connection's handshake to complete before aborting it.
+    pub handshake_timeout: Option<Duration>,
 }
 
 impl EndpointConfigBuilder {
@@ -378,16 +1092,46 @@ impl EndpointConfigBuilder {
         self
     }
 
+    pub fn alpn_protocols(mut self, alpn_protocols: Vec<Vec<u8>>) -> Self {
+        self.alpn_protocols = alpn_protocols;
+        self
+    }
+
     pub fn transport_config(mut self, transport_config: quinn::TransportConfig) -> Self {
         self.transport_config = Some(transport_config);
         self
     }
 
+    pub fn congestion_controller(mut self, congestion_controller: CongestionController) -> Self {
+        self.congestion_controller = Some(congestion_controller);
+        self
+    }
+
+    pub fn enable_session_resumption(mut self, enable_session_resumption: bool) -> Self {
+        self.enable_session_resumption = enable_session_resumption;
+        self
+    }
+
+    pub fn require_address_validation(mut self, require_address_validation: bool) -> Self {
+        self.require_address_validation = require_address_validation;
+        self
+    }
+
+    pub fn allowed_peers(mut self, allowed_peers: Option<HashSet<PeerId>>) -> Self {
+        self.allowed_peers = allowed_peers;
+        self
+    }
+
     pub fn private_key(mut self, private_key: [u8; 32]) -> Self {
         self.private_key = Some(private_key);
         self
     }
 
+    pub fn handshake_timeout(mut self, handshake_timeout: Option<Duration>) -> Self {
+        self.handshake_timeout = handshake_timeout;
+        self
+    }
+
     #[cfg(test)]
     pub(crate) fn random_private_key(self) -> Self {
         let mut rng = rand::thread_rng();
@@ -410,13 +1154,24 @@ impl EndpointConfigBuilder {
         let quinn_endpoint_config = quinn::EndpointConfig::new(Arc::new(reset_key));
 
         let primary_server_name = self.server_name.unwrap();
-        let transport_config = Arc::new(self.transport_config.unwrap_or_default());
+        let mut transport_config = self.transport_config.unwrap_or_default();
+        if let Some(congestion_controller) = self.congestion_controller {
+            congestion_controller.apply(&mut transport_config);
+        }
+        let transport_config = Arc::new(transport_config);
 
+        let allowed_peers = self.allowed_peers.map(Arc::new);
         let cert_verifier = Arc::new(CertVerifier {
             server_names: vec![primary_server_name.clone()],
+            allowed_peers: allowed_peers.clone(),
         });
         let (primary_certificate, pkcs8_der) = Self::generate_cert(&keypair, &primary_server_name);
 
+        // Sessions are shared by all client configs so that they can be resumed by any of them
+        let resumption = self
+            .enable_session_resumption
+            .then(|| rustls::client::Resumption::in_memory_sessions(256));
+
         // Client only uses the primary `server_name` when initiating outbound connections
         // so only needs the primary certificate.
         let client_config = Self::client_config(
@@ -424,15 +1179,18 @@ impl EndpointConfigBuilder {
             pkcs8_der.clone(),
             cert_verifier.clone(),
             transport_config.clone(),
+            resumption.as_ref(),
+            &self.alpn_protocols,
         )?;
 
         let alternate_server_name = self.alternate_server_name;
-        let server_config = match alternate_server_name {
+        let mut server_config = match alternate_server_name {
             Some(alternate_server_name) => {
                 let (alternate_certificate, _) =
                     Self::generate_cert(&keypair, &alternate_server_name);
                 let cert_verifier = Arc::new(CertVerifier {
                     server_names: vec![primary_server_name.clone(), alternate_server_name.clone()],
+                    allowed_peers: allowed_peers.clone(),
                 });
                 Self::server_config(
                     vec![
@@ -442,6 +1200,8 @@ impl EndpointConfigBuilder {
                     pkcs8_der.clone(),
                     cert_verifier,
                     transport_config.clone(),
+                    self.enable_session_resumption,
+                    &self.alpn_protocols,
                 )
             }
             _ => Self::server_config(
@@ -449,8 +1209,11 @@ impl EndpointConfigBuilder {
                 pkcs8_der.clone(),
                 cert_verifier,
                 transport_config.clone(),
+                self.enable_session_resumption,
+                &self.alpn_protocols,
             ),
         }?;
+        server_config.use_retry(self.require_address_validation);
 
         let peer_id = crate::crypto::peer_id_from_certificate(&primary_certificate).unwrap();
 
@@ -463,6 +1226,10 @@ impl EndpointConfigBuilder {
             server_name: primary_server_name,
             transport_config,
             quinn_endpoint_config,
+            resumption,
+            allowed_peers,
+            alpn_protocols: self.alpn_protocols,
+            handshake_timeout: self.handshake_timeout,
         })
     }
 
@@ -482,6 +1249,8 @@ impl EndpointConfigBuilder {
         pkcs8_der: rustls::PrivateKey,
         cert_verifier: Arc<CertVerifier>,
         transport_config: Arc<quinn::TransportConfig>,
+        enable_session_resumption: bool,
+        alpn_protocols: &[Vec<u8>],
     ) -> Result<quinn::ServerConfig> {
         let mut server_cert_resolver = rustls::server::ResolvesServerCertUsingSni::new();
         let key = rustls::sign::any_supported_type(&pkcs8_der)
@@ -491,10 +1260,16 @@ impl EndpointConfigBuilder {
             server_cert_resolver.add(&server_name, certified_key)?;
         }
 
-        let server_crypto = rustls::ServerConfig::builder()
+        let mut server_crypto = rustls::ServerConfig::builder()
             .with_safe_defaults()
             .with_client_cert_verifier(cert_verifier)
             .with_cert_resolver(Arc::new(server_cert_resolver));
+        if !enable_session_resumption {
+            // Don't hand out any tickets with which clients could resume their session
+            server_crypto.send_tls13_tickets = 0;
+        }
+        // QUIC requires that the peers agree on an ALPN protocol if either of them uses ALPN
+        server_crypto.alpn_protocols = alpn_protocols.to_vec();
 
         let mut server = quinn::ServerConfig::with_crypto(Arc::new(server_crypto));
         server.transport = transport_config;
@@ -504,13 +1279,19 @@ impl EndpointConfigBuilder {
     fn client_config(
         cert: rustls::Certificate,
         pkcs8_der: rustls::PrivateKey,
-        cert_verifier: Arc<CertVerifier>,
+        cert_verifier: Arc<dyn rustls::client::ServerCertVerifier>,
         transport_config: Arc<quinn::TransportConfig>,
+        resumption: Option<&rustls::client::Resumption>,
+        alpn_protocols: &[Vec<u8>],
     ) -> Result<quinn::ClientConfig> {
-        let client_crypto = rustls::ClientConfig::builder()
+        let mut client_crypto = rustls::ClientConfig::builder()
             .with_safe_defaults()
             .with_custom_certificate_verifier(cert_verifier)
-            .with_single_cert(vec![cert], pkcs8_der)?;
+            .with_client_auth_cert(vec![cert], pkcs8_der)?;
+        client_crypto.resumption = resumption
+            .cloned()
+            .unwrap_or_else(rustls::client::Resumption::disabled);
+        client_crypto.alpn_protocols = alpn_protocols.to_vec();
 
         let mut client = quinn::ClientConfig::new(Arc::new(client_crypto));
         client.transport_config(transport_config);
@@ -518,7 +1299,7 @@ impl EndpointConfigBuilder {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct EndpointConfig {
     peer_id: PeerId,
     // Store client certificate for outbound connections initiation
@@ -534,6 +1315,18 @@ pub(crate) struct EndpointConfig {
 
     transport_config: Arc<quinn::TransportConfig>,
     quinn_endpoint_config: quinn::EndpointConfig,
+
+    /// Store of sessions which can be resumed, if enabled
+    resumption: Option<rustls::client::Resumption>,
+
+    /// The only peers we're allowed to connect with, if set
+    allowed_peers: Option<Arc<HashSet<PeerId>>>,
+
+    /// ALPN protocols to offer and accept, in order of preference
+    alpn_protocols: Vec<Vec<u8>>,
+
+    /// How long to wait for a connection's handshake to complete before aborting it, if set
+    handshake_timeout: Option<Duration>,
 }
 
 impl EndpointConfig {
@@ -549,6 +1342,10 @@ impl EndpointConfig {
         &self.server_name
     }
 
+    pub fn handshake_timeout(&self) -> Option<Duration> {
+        self.handshake_timeout
+    }
+
     pub fn quinn_endpoint_config(&self) -> quinn::EndpointConfig {
         self.quinn_endpoint_config.clone()
     }
@@ -557,32 +1354,47 @@ impl EndpointConfig {
         &self.quinn_server_config
     }
 
-    pub fn client_config(&self) -> &quinn::ClientConfig {
-        &self.quinn_client_config
-    }
-
-    pub fn client_config_with_expected_server_identity(
+    /// Returns the client config to use for an outbound connection, verifying that the server's
+    /// identity is `peer_id`, if provided.
+    ///
+    /// If session resumption is enabled, a flag is also returned which is set once the server's
+    /// certificate has been verified. A resumed session skips verification, as the certificate
+    /// was already verified when the session was established, so this tells the two apart.
+    pub fn client_config_for_connection(
         &self,
-        peer_id: PeerId,
-    ) -> quinn::ClientConfig {
-        let server_cert_verifier = ExpectedCertVerifier(
-            CertVerifier {
-                server_names: vec![self.server_name().into()],
-            },
-            peer_id,
-        );
-        let client_crypto = rustls::ClientConfig::builder()
-            .with_safe_defaults()
-            .with_custom_certificate_verifier(Arc::new(server_cert_verifier))
-            .with_single_cert(
-                vec![self.client_certificate.clone()],
-                self.pkcs8_der.clone(),
-            )
-            .unwrap();
+        peer_id: Option<PeerId>,
+    ) -> (quinn::ClientConfig, Option<Arc<AtomicBool>>) {
+        if peer_id.is_none() && self.resumption.is_none() {
+            return (self.quinn_client_config.clone(), None);
+        }
 
-        let mut client = quinn::ClientConfig::new(Arc::new(client_crypto));
-        client.transport_config(self.transport_config.clone());
-        client
+        let cert_verifier = CertVerifier {
+            server_names: vec![self.server_name().into()],
+            allowed_peers: self.allowed_peers.clone(),
+        };
+        let mut cert_verifier: Arc<dyn rustls::client::ServerCertVerifier> = match peer_id {
+            Some(peer_id) => Arc::new(ExpectedCertVerifier(cert_verifier, peer_id)),
+            None => Arc::new(cert_verifier),
+        };
+        let certificate_verified = self.resumption.as_ref().map(|_| {
+            let verified = Arc::new(AtomicBool::new(false));
+            cert_verifier = Arc::new(RecordingCertVerifier {
+                inner: cert_verifier.clone(),
+                verified: verified.clone(),
+            });
+            verified
+        });
+
+        let client = EndpointConfigBuilder::client_config(
+            self.client_certificate.clone(),
+            self.pkcs8_der.clone(),
+            cert_verifier,
+            self.transport_config.clone(),
+            self.resumption.as_ref(),
+            &self.alpn_protocols,
+        )
+        .unwrap();
+        (client, certificate_verified)
     }
 
     #[cfg(test)]
@@ -1,50 +1,180 @@
-use crate::{ConnectionOrigin, PeerId, Result};
+use crate::{
+    bandwidth::{BandwidthLimiter, Throttle},
+    types::Version,
+    ConnectionOrigin, PeerId, Result,
+};
+use bytes::Bytes;
 use quinn::{ConnectionError, RecvStream};
 use quinn_proto::ConnectionStats;
 use std::{
+    collections::{HashMap, HashSet},
     fmt, io,
     net::SocketAddr,
     pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task::{Context, Poll},
     time::Duration,
 };
 use tracing::trace;
 
+/// Application error codes used when closing a connection.
+///
+/// Codes below [`close_code::RESERVED`] are reserved for anemo itself, leaving the rest to
+/// applications closing connections via
+/// [`Network::disconnect_with_reason`](crate::Network::disconnect_with_reason). New codes used by
+/// anemo must be allocated from the reserved range.
+pub(crate) mod close_code {
+    /// Codes below this are reserved for anemo.
+    pub const RESERVED: u32 = 256;
+
+    /// The connection was closed without a more specific reason.
+    pub const CLOSED: u32 = 0;
+    /// The connection was refused due to connection limits.
+    pub const CONNECTION_LIMIT: u32 = 1;
+    /// The connection was refused or closed because the peer is banned.
+    pub const BANNED: u32 = 2;
+    /// The connection was refused because the peers have no wire protocol version in common.
+    pub const VERSION_MISMATCH: u32 = 3;
+    /// The connection was rejected by an application provided connect hook.
+    pub const REJECTED: u32 = 4;
+    /// The connection was refused because it was made with ourself.
+    pub const SELF_CONNECTION: u32 = 5;
+    /// The connection was closed in favor of another connection with the same peer.
+    pub const DUPLICATE_CONNECTION: u32 = 6;
+    /// The connection was refused because the network is in drain mode.
+    pub const DRAINING: u32 = 7;
+    /// The connection was refused because the peers belong to different networks, see
+    /// [`Config::network_id`](crate::Config::network_id).
+    pub const NETWORK_MISMATCH: u32 = 8;
+}
+
 #[derive(Clone)]
 pub(crate) struct Connection {
     inner: quinn::Connection,
     peer_id: PeerId,
     origin: ConnectionOrigin,
 
+    // End-entity certificate the peer presented during the TLS handshake
+    peer_certificate: Arc<rustls::Certificate>,
+
     // Time that the connection was established
     time_established: std::time::Instant,
+
+    // Number of inbound requests from the peer which are currently being processed
+    inflight_inbound_requests: Arc<AtomicUsize>,
+    queued_inbound_requests: Arc<AtomicUsize>,
+    outbound_streams: Arc<AtomicUsize>,
+
+    // Number of streams, in either direction, which have been opened and closed over the lifetime
+    // of the connection
+    streams_opened: Arc<AtomicU64>,
+    streams_closed: Arc<AtomicU64>,
+
+    // Time of the most recent request in either direction, in milliseconds since
+    // `time_established`
+    last_activity_ms: Arc<AtomicU64>,
+
+    // Paces the bytes written to all streams sent to the peer
+    bandwidth_limiter: Arc<BandwidthLimiter>,
+
+    // Whether the connection resumed a previous TLS session with the peer
+    resumed: bool,
+
+    // Wire protocol version negotiated with the peer during the handshake
+    version: Version,
+
+    // Routes the peer advertised during the handshake
+    capabilities: Arc<HashSet<String>>,
+
+    // Application provided key/value metadata about the peer
+    metadata: Arc<Mutex<HashMap<String, String>>>,
 }
 
 impl Connection {
     pub fn new(inner: quinn::Connection, origin: ConnectionOrigin) -> Result<Self> {
-        let peer_id = Self::try_peer_id(&inner)?;
+        let peer_certificate = Self::try_peer_certificate(&inner)?;
+        let peer_id = crate::crypto::peer_id_from_certificate(&peer_certificate)?;
         Ok(Self {
             inner,
             peer_id,
             origin,
+            peer_certificate: Arc::new(peer_certificate),
             time_established: std::time::Instant::now(),
+            inflight_inbound_requests: Default::default(),
+            queued_inbound_requests: Default::default(),
+            outbound_streams: Default::default(),
+            streams_opened: Default::default(),
+            streams_closed: Default::default(),
+            last_activity_ms: Default::default(),
+            bandwidth_limiter: Arc::new(BandwidthLimiter::new(None)),
+            resumed: false,
+            version: Version::LATEST,
+            capabilities: Default::default(),
+            metadata: Default::default(),
         })
     }
 
-    /// Try to query Cryptographic identity of the peer
-    fn try_peer_id(connection: &quinn::Connection) -> Result<PeerId> {
+    /// Try to query the certificate carrying the Cryptographic identity of the peer
+    fn try_peer_certificate(connection: &quinn::Connection) -> Result<rustls::Certificate> {
         // Query the certificate chain provided by a [TLS
         // Connection](https://docs.rs/rustls/0.20.4/rustls/enum.Connection.html#method.peer_certificates).
         // The first cert in the chain is guaranteed to be the peer
-        let peer_cert = &connection
+        let peer_cert = connection
             .peer_identity()
-            .unwrap()
+            .ok_or_else(|| anyhow::anyhow!("peer did not provide an identity"))?
             .downcast::<Vec<rustls::Certificate>>()
-            .unwrap()[0];
+            .map_err(|_| anyhow::anyhow!("peer provided an unexpected identity"))?
+            .swap_remove(0);
+
+        Ok(peer_cert)
+    }
+
+    pub(crate) fn with_resumed(mut self, resumed: bool) -> Self {
+        self.resumed = resumed;
+        self
+    }
+
+    /// Whether the Connection resumed a previous TLS session with the peer
+    pub fn resumed(&self) -> bool {
+        self.resumed
+    }
+
+    pub(crate) fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The wire protocol version negotiated with the peer
+    pub fn version(&self) -> Version {
+        self.version
+    }
 
-        let peer_id = crate::crypto::peer_id_from_certificate(peer_cert)?;
+    pub(crate) fn with_capabilities(mut self, capabilities: HashSet<String>) -> Self {
+        self.capabilities = Arc::new(capabilities);
+        self
+    }
+
+    /// The routes the peer advertised during the handshake
+    pub fn capabilities(&self) -> &HashSet<String> {
+        &self.capabilities
+    }
+
+    /// A snapshot of the metadata attached to the Connection
+    pub fn metadata(&self) -> HashMap<String, String> {
+        self.metadata.lock().unwrap().clone()
+    }
+
+    /// Attach `value` to the Connection under `key`, replacing any previous value
+    pub fn set_metadata(&self, key: String, value: String) {
+        self.metadata.lock().unwrap().insert(key, value);
+    }
 
-        Ok(peer_id)
+    /// DER encoded end-entity certificate the Remote Peer presented during the TLS handshake
+    pub fn peer_certificate(&self) -> &[u8] {
+        &self.peer_certificate.0
     }
 
     /// PeerId of the Remote Peer
@@ -58,11 +188,113 @@ impl Connection {
     }
 
     /// Time the Connection was established
-    #[allow(unused)]
     pub fn time_established(&self) -> std::time::Instant {
         self.time_established
     }
 
+    /// Number of inbound requests from the peer which are currently being processed
+    pub fn inflight_inbound_requests(&self) -> usize {
+        self.inflight_inbound_requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of inbound requests from the peer which are waiting to be processed
+    pub fn queued_inbound_requests(&self) -> usize {
+        self.queued_inbound_requests.load(Ordering::Relaxed)
+    }
+
+    /// Time of the most recent request or response sent or received over the Connection, or the
+    /// time the Connection was established if there hasn't been any activity
+    pub fn last_activity(&self) -> std::time::Instant {
+        self.time_established + Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed))
+    }
+
+    /// Record that data was just successfully sent or received over the Connection
+    pub fn record_activity(&self) {
+        let elapsed = self.time_established.elapsed().as_millis() as u64;
+        self.last_activity_ms.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    /// Number of streams opened to the peer which are still open
+    pub fn outbound_streams(&self) -> usize {
+        self.outbound_streams.load(Ordering::Relaxed)
+    }
+
+    /// Total number of streams, in either direction, opened over the lifetime of the connection
+    pub fn streams_opened(&self) -> u64 {
+        self.streams_opened.load(Ordering::Relaxed)
+    }
+
+    /// Total number of streams, in either direction, closed over the lifetime of the connection
+    pub fn streams_closed(&self) -> u64 {
+        self.streams_closed.load(Ordering::Relaxed)
+    }
+
+    /// Limit the bytes written to streams sent to the peer to `rate` bytes per second, or remove
+    /// the limit if `None`
+    pub fn set_outbound_bandwidth_limit(&self, rate: Option<u64>) {
+        self.bandwidth_limiter.set_rate(rate)
+    }
+
+    /// The limit on the bytes per second written to streams sent to the peer, if any
+    pub fn outbound_bandwidth_limit(&self) -> Option<u64> {
+        self.bandwidth_limiter.rate()
+    }
+
+    /// Total number of bytes written to streams sent to the peer
+    pub fn outbound_bytes_sent(&self) -> u64 {
+        self.bandwidth_limiter.bytes_sent()
+    }
+
+    fn new_send_stream(
+        &self,
+        send_stream: quinn::SendStream,
+        stream_guard: Option<StreamGuard>,
+    ) -> SendStream {
+        SendStream(
+            send_stream,
+            None,
+            stream_guard,
+            Throttle::new(self.bandwidth_limiter.clone()),
+        )
+    }
+
+    /// Count a stream as opened, and then as closed once the returned guard is dropped
+    pub fn track_stream(&self) -> StreamGuard {
+        self.streams_opened.fetch_add(1, Ordering::Relaxed);
+        StreamGuard(self.streams_closed.clone())
+    }
+
+    /// Reserve one of at most `limit` outbound streams until the returned guard is dropped.
+    ///
+    /// Returns `None` if `limit` streams are already open.
+    pub fn reserve_outbound_stream(&self, limit: Option<usize>) -> Option<CounterGuard> {
+        self.outbound_streams
+            .fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |streams| match limit {
+                    Some(limit) if streams >= limit => None,
+                    _ => Some(streams + 1),
+                },
+            )
+            .ok()?;
+        Some(CounterGuard(self.outbound_streams.clone()))
+    }
+
+    /// Mark an inbound request as queued until the returned guard is dropped
+    pub fn queue_inbound_request(&self) -> CounterGuard {
+        self.queued_inbound_requests.fetch_add(1, Ordering::Relaxed);
+        CounterGuard(self.queued_inbound_requests.clone())
+    }
+
+    /// Mark an inbound request as in-flight until the returned guard is dropped
+    pub fn start_inbound_request(&self) -> CounterGuard {
+        self.record_activity();
+        self.inflight_inbound_requests
+            .fetch_add(1, Ordering::Relaxed);
+        CounterGuard(self.inflight_inbound_requests.clone())
+    }
+
     /// A stable identifier for this connection
     ///
     /// Peer addresses and connection IDs can change, but this value will remain
@@ -93,9 +325,12 @@ impl Connection {
     /// Open a unidirection stream to the peer.
     ///
     /// Messages sent over the stream will arrive at the peer in the order they were sent.
-    #[allow(dead_code)]
     pub async fn open_uni(&self) -> Result<SendStream, ConnectionError> {
-        self.inner.open_uni().await.map(SendStream)
+        self.record_activity();
+        self.inner
+            .open_uni()
+            .await
+            .map(|send| self.new_send_stream(send, Some(self.track_stream())))
     }
 
     /// Open a bidirectional stream to the peer.
@@ -105,10 +340,11 @@ impl Connection {
     ///
     /// Messages sent over the stream will arrive at the peer in the order they were sent.
     pub async fn open_bi(&self) -> Result<(SendStream, RecvStream), ConnectionError> {
+        self.record_activity();
         self.inner
             .open_bi()
             .await
-            .map(|(send, recv)| (SendStream(send), recv))
+            .map(|(send, recv)| (self.new_send_stream(send, Some(self.track_stream())), recv))
     }
 
     /// Close the connection immediately.
@@ -116,8 +352,20 @@ impl Connection {
     /// This is not a graceful close - pending operations will fail immediately and data on
     /// unfinished streams is not guaranteed to be delivered.
     pub fn close(&self) {
-        trace!("Closing Connection");
-        self.inner.close(0_u32.into(), b"connection closed")
+        self.close_with_reason(close_code::CLOSED, b"connection closed")
+    }
+
+    /// Close the connection immediately, providing the remote side with an application error
+    /// `code` and `reason`.
+    pub fn close_with_reason<C: Into<quinn::VarInt>>(&self, code: C, reason: &[u8]) {
+        let code = code.into();
+        trace!("Closing Connection with code {code}");
+        self.inner.close(code, reason)
+    }
+
+    /// If the connection is closed, the reason why.
+    pub fn close_reason(&self) -> Option<ConnectionError> {
+        self.inner.close_reason()
     }
 
     /// Accept the next incoming uni-directional stream
@@ -126,11 +374,28 @@ impl Connection {
     }
 
     /// Accept the next incoming bidirectional stream
+    ///
+    /// Unlike outbound streams, incoming streams aren't tracked automatically; see
+    /// [`Connection::track_stream`].
     pub async fn accept_bi(&self) -> Result<(SendStream, RecvStream), ConnectionError> {
         self.inner
             .accept_bi()
             .await
-            .map(|(send, recv)| (SendStream(send), recv))
+            .map(|(send, recv)| (self.new_send_stream(send, None), recv))
+    }
+
+    /// Transmit an application datagram
+    pub fn send_datagram(&self, data: bytes::Bytes) -> Result<(), quinn::SendDatagramError> {
+        self.inner.send_datagram(data)
+    }
+
+    /// Compute the maximum size of datagrams that may be passed to [`send_datagram()`].
+    ///
+    /// Returns `None` if datagrams are unsupported by the peer or disabled locally.
+    ///
+    /// [`send_datagram()`]: Connection::send_datagram
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        self.inner.max_datagram_size()
     }
 
     /// Receive an application datagram
@@ -150,15 +415,79 @@ impl fmt::Debug for Connection {
     }
 }
 
+/// Holds one unit of a connection's counter, e.g. a reserved outbound stream or a queued or
+/// in-flight inbound request, releasing it once dropped.
+pub(crate) struct CounterGuard(Arc<AtomicUsize>);
+
+impl Drop for CounterGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a stream as closed on its connection once dropped.
+pub(crate) struct StreamGuard(Arc<AtomicU64>);
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 /// A wrapper around a [quinn::SendStream] that enforces that the stream is shut down immediately
 /// when dropped. The proper way to ensure that all data has been successfully transmitted and
 /// Ack'd by the remote side is to call [quinn::SendStream::finish] prior to dropping the stream.
-pub(crate) struct SendStream(quinn::SendStream);
+///
+/// Writes are paced according to the connection's outbound bandwidth limit, if any.
+pub(crate) struct SendStream(
+    quinn::SendStream,
+    Option<CounterGuard>,
+    Option<StreamGuard>,
+    Throttle,
+);
+
+impl SendStream {
+    /// Hold on to `guard` until this stream is dropped
+    pub fn with_guard(mut self, guard: CounterGuard) -> Self {
+        self.1 = Some(guard);
+        self
+    }
+
+    /// Write `chunks` to the stream without copying them, paced according to the connection's
+    /// outbound bandwidth limit.
+    ///
+    /// Unlike writes through [`AsyncWrite`](tokio::io::AsyncWrite), the chunks are handed to
+    /// quinn as-is, so any buffers they share are kept alive until the data has been sent. Each
+    /// chunk is split into pieces no larger than the budget granted at the time, and only the
+    /// pieces which were actually written are charged against the limit.
+    pub async fn write_all_chunks(
+        &mut self,
+        chunks: &mut [Bytes],
+    ) -> Result<(), quinn::WriteError> {
+        for chunk in chunks.iter_mut() {
+            while !chunk.is_empty() {
+                let granted =
+                    futures::future::poll_fn(|cx| self.3.poll_acquire(cx, chunk.len())).await;
+                let piece = chunk.split_to(granted);
+                match self.0.write_chunk(piece).await {
+                    Ok(()) => self.3.complete(granted, granted),
+                    Err(e) => {
+                        self.3.complete(granted, 0);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
 
 impl Drop for SendStream {
     fn drop(&mut self) {
         // We don't care if the stream has already been closed
         let _ = self.0.reset(0u8.into());
+        // Only count the stream as closed once it has actually been reset
+        drop(self.2.take());
     }
 }
 
@@ -182,7 +511,15 @@ impl tokio::io::AsyncWrite for SendStream {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.0).poll_write(cx, buf)
+        let this = &mut *self;
+        let granted = futures::ready!(this.3.poll_acquire(cx, buf.len()));
+        let result = Pin::new(&mut this.0).poll_write(cx, &buf[..granted]);
+        let written = match &result {
+            Poll::Ready(Ok(written)) => *written,
+            _ => 0,
+        };
+        this.3.complete(granted, written);
+        result
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
@@ -1,14 +1,18 @@
 use crate::{
-    config::EndpointConfig, connection::Connection, types::Address, ConnectionOrigin, PeerId,
-    Result,
+    config::EndpointConfig, connection::Connection, types::Address, ConnectError, ConnectionOrigin,
+    PeerId, PeerNotAllowed, Result,
 };
+use futures::{future::BoxFuture, FutureExt, TryFutureExt};
 use std::sync::Arc;
 use std::time::Duration;
 use std::{
     future::Future,
     net::SocketAddr,
     pin::Pin,
-    sync::RwLock,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
     task::{Context, Poll},
 };
 use tap::Pipe;
@@ -53,7 +57,7 @@ impl Endpoint {
     }
 
     pub fn connect(&self, address: Address) -> Result<Connecting> {
-        self.connect_with_client_config(self.config.client_config().clone(), address)
+        self.connect_with_expected_identity(address, None)
     }
 
     pub fn connect_with_expected_peer_id(
@@ -61,23 +65,29 @@ impl Endpoint {
         address: Address,
         peer_id: PeerId,
     ) -> Result<Connecting> {
-        let config = self
-            .config
-            .client_config_with_expected_server_identity(peer_id);
-        self.connect_with_client_config(config, address)
+        self.connect_with_expected_identity(address, Some(peer_id))
     }
 
-    fn connect_with_client_config(
+    fn connect_with_expected_identity(
         &self,
-        config: quinn::ClientConfig,
         address: Address,
+        expected_peer_id: Option<PeerId>,
     ) -> Result<Connecting> {
         let addr = address.resolve()?;
+        let (config, certificate_verified) =
+            self.config.client_config_for_connection(expected_peer_id);
 
         self.inner
             .connect_with(config, addr, self.config.server_name())
             .map_err(Into::into)
-            .map(Connecting::new_outbound)
+            .map(|connecting| {
+                Connecting::new_outbound(
+                    connecting,
+                    expected_peer_id,
+                    certificate_verified,
+                    self.config.handshake_timeout(),
+                )
+            })
     }
 
     /// Returns the socket address that this Endpoint is bound to.
@@ -99,6 +109,12 @@ impl Endpoint {
         self.inner.close(0_u32.into(), b"endpoint closed")
     }
 
+    /// Cease accepting new connections while leaving existing connections untouched.
+    pub fn reject_new_connections(&self) {
+        trace!("Rejecting new connections");
+        self.inner.set_server_config(None)
+    }
+
     /// Wait for all connections on the endpoint to be cleanly shut down
     ///
     /// Waiting for this condition before exiting ensures that a good-faith effort is made to notify
@@ -142,6 +158,7 @@ impl Endpoint {
     pub(crate) fn accept(&self) -> Accept<'_> {
         Accept {
             inner: self.inner.accept(),
+            handshake_timeout: self.config.handshake_timeout(),
         }
     }
 }
@@ -152,6 +169,7 @@ pin_project_lite::pin_project! {
     pub(crate) struct Accept<'a> {
         #[pin]
         inner: quinn::Accept<'a>,
+        handshake_timeout: Option<Duration>,
     }
 }
 
@@ -159,31 +177,152 @@ impl<'a> Future for Accept<'a> {
     type Output = Option<Connecting>;
 
     fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.project()
-            .inner
-            .poll(ctx)
-            .map(|maybe_connecting| maybe_connecting.map(Connecting::new_inbound))
+        let this = self.project();
+        this.inner.poll(ctx).map(|maybe_connecting| {
+            maybe_connecting
+                .map(|connecting| Connecting::new_inbound(connecting, *this.handshake_timeout))
+        })
     }
 }
 
-#[derive(Debug)]
 #[must_use = "futures/streams/sinks do nothing unless you `.await` or poll them"]
 pub(crate) struct Connecting {
-    inner: quinn::Connecting,
-    origin: ConnectionOrigin,
+    inner: BoxFuture<'static, Result<Connection>>,
+    remote_address: SocketAddr,
 }
 
 impl Connecting {
-    pub(crate) fn new(inner: quinn::Connecting, origin: ConnectionOrigin) -> Self {
-        Self { inner, origin }
+    /// Establish a connection, which resumed a previous session if `certificate_verified` is
+    /// provided but wasn't set during the handshake. The attempt is aborted if the handshake
+    /// doesn't complete within `handshake_timeout`, if set.
+    ///
+    /// Failures are described by a [`ConnectError`], with `expected_peer_id` being the identity
+    /// the peer was required to have, if any.
+    fn new(
+        inner: quinn::Connecting,
+        origin: ConnectionOrigin,
+        expected_peer_id: Option<PeerId>,
+        certificate_verified: Option<Arc<AtomicBool>>,
+        handshake_timeout: Option<Duration>,
+    ) -> Self {
+        let remote_address = inner.remote_address();
+        let handshake = async move {
+            let connection = inner
+                .await
+                .map_err(|e| connection_error(e, expected_peer_id))?;
+            let resumed = certificate_verified
+                .is_some_and(|certificate_verified| !certificate_verified.load(Ordering::Relaxed));
+
+            Connection::new(connection, origin).map(|connection| connection.with_resumed(resumed))
+        };
+        let inner = async move {
+            match handshake_timeout {
+                // Dropping the handshake abandons the connection attempt
+                Some(handshake_timeout) => timeout(handshake_timeout, handshake)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(anyhow::Error::new(ConnectError::Timeout).context(format!(
+                            "handshake did not complete within {handshake_timeout:?}"
+                        )))
+                    }),
+                None => handshake.await,
+            }
+        }
+        .map_err(move |e| {
+            if e.is::<ConnectError>() {
+                e
+            } else {
+                anyhow::anyhow!("failed establishing {origin} connection: {e}")
+            }
+        })
+        .boxed();
+
+        Self {
+            inner,
+            remote_address,
+        }
     }
 
-    pub(crate) fn new_inbound(inner: quinn::Connecting) -> Self {
-        Self::new(inner, ConnectionOrigin::Inbound)
+    pub(crate) fn new_inbound(
+        inner: quinn::Connecting,
+        handshake_timeout: Option<Duration>,
+    ) -> Self {
+        Self::new(
+            inner,
+            ConnectionOrigin::Inbound,
+            None,
+            None,
+            handshake_timeout,
+        )
     }
 
-    pub(crate) fn new_outbound(inner: quinn::Connecting) -> Self {
-        Self::new(inner, ConnectionOrigin::Outbound)
+    pub(crate) fn new_outbound(
+        inner: quinn::Connecting,
+        expected_peer_id: Option<PeerId>,
+        certificate_verified: Option<Arc<AtomicBool>>,
+        handshake_timeout: Option<Duration>,
+    ) -> Self {
+        Self::new(
+            inner,
+            ConnectionOrigin::Outbound,
+            expected_peer_id,
+            certificate_verified,
+            handshake_timeout,
+        )
+    }
+
+    pub(crate) fn remote_address(&self) -> SocketAddr {
+        self.remote_address
+    }
+}
+
+/// Convert an error encountered while establishing a connection into one describing it with a
+/// [`ConnectError`], also surfacing a [`PeerNotAllowed`] error if the handshake failed due to the
+/// peer not being allowed.
+fn connection_error(
+    error: quinn::ConnectionError,
+    expected_peer_id: Option<PeerId>,
+) -> anyhow::Error {
+    // TLS `no_application_protocol` alert, sent when the peers have no ALPN protocol in common
+    const NO_APPLICATION_PROTOCOL: u8 = 120;
+    let no_application_protocol = quinn_proto::TransportErrorCode::crypto(NO_APPLICATION_PROTOCOL);
+
+    match (&error, expected_peer_id) {
+        (quinn::ConnectionError::TransportError(e), _)
+            if e.reason
+                .contains(&crate::crypto::PEER_NOT_ALLOWED.to_string()) =>
+        {
+            let e = PeerNotAllowed(());
+            let connect_error = ConnectError::TlsError(e.to_string());
+            anyhow::Error::new(e).context(connect_error)
+        }
+        (quinn::ConnectionError::TransportError(e), Some(expected))
+            if e.reason.contains(crate::crypto::UNEXPECTED_PEER_ID) =>
+        {
+            ConnectError::PeerIdMismatch { expected }.into()
+        }
+        (quinn::ConnectionError::TransportError(quinn_proto::TransportError { code, .. }), _)
+        | (
+            quinn::ConnectionError::ConnectionClosed(quinn_proto::ConnectionClose {
+                error_code: code,
+                ..
+            }),
+            _,
+        ) if *code == no_application_protocol => {
+            ConnectError::TlsError(format!("no ALPN protocol in common with peer: {error}")).into()
+        }
+        _ => {
+            let connect_error = ConnectError::from_connection_error(&error);
+            anyhow::Error::new(error).context(connect_error)
+        }
+    }
+}
+
+impl std::fmt::Debug for Connecting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connecting")
+            .field("remote_address", &self.remote_address)
+            .finish_non_exhaustive()
     }
 }
 
@@ -191,12 +330,7 @@ impl Future for Connecting {
     type Output = Result<Connection>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        Pin::new(&mut self.inner).poll(cx).map(|result| {
-            result
-                .map_err(anyhow::Error::from)
-                .and_then(|connection| Connection::new(connection, self.origin))
-                .map_err(|e| anyhow::anyhow!("failed establishing {} connection: {e}", self.origin))
-        })
+        self.inner.poll_unpin(cx)
     }
 }
 
@@ -1,5 +1,8 @@
+pub use crate::middleware::timeout::TimeoutExpired;
 pub use anyhow::{Error, Result};
 
+use crate::PeerId;
+
 /// Alias for a type-erased error type.
 pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
@@ -7,3 +10,245 @@ pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
 #[derive(thiserror::Error, Debug)]
 #[error(transparent)]
 pub struct AsStdError(#[from] anyhow::Error);
+
+/// Error returned when a connection is refused because the peer isn't in
+/// [`Config::allowed_peers`](crate::Config::allowed_peers).
+#[derive(thiserror::Error, Debug)]
+#[error("peer is not in the set of allowed peers")]
+pub struct PeerNotAllowed(pub(crate) ());
+
+/// Error returned when a connection is refused because the peer on the other end turned out to be
+/// ourself, e.g. as a result of dialing our own address.
+#[derive(thiserror::Error, Debug)]
+#[error("refusing to connect to ourself")]
+pub struct SelfConnection(pub(crate) ());
+
+/// Describes why connecting to a peer via [`Network::connect`](crate::Network::connect),
+/// [`Network::connect_with_timeout`](crate::Network::connect_with_timeout) or
+/// [`Network::connect_with_peer_id`](crate::Network::connect_with_peer_id) failed.
+///
+/// The errors returned when connecting can be downcast to a `ConnectError`, e.g. via
+/// `error.downcast_ref::<ConnectError>()`, in order to decide whether and when to retry.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnectError {
+    /// The connection wasn't established within the configured connect or handshake timeout, or
+    /// the timeout passed to [`Network::connect_with_timeout`](crate::Network::connect_with_timeout).
+    #[error("connection attempt timed out")]
+    Timeout,
+
+    /// The connection was refused, either by the peer or by us, e.g. due to connection limits, a
+    /// connect hook, or the peers belonging to different networks.
+    #[error("connection refused: {0}")]
+    Refused(String),
+
+    /// The peers have no wire protocol version in common.
+    #[error("no common protocol version")]
+    VersionMismatch,
+
+    /// The TLS handshake failed, e.g. because the peer isn't in
+    /// [`Config::allowed_peers`](crate::Config::allowed_peers) or the peers have no ALPN protocol
+    /// in common.
+    #[error("TLS handshake failed: {0}")]
+    TlsError(String),
+
+    /// The peer's identity didn't match the one which was expected.
+    #[error("peer's identity doesn't match the expected peer {expected}")]
+    PeerIdMismatch { expected: PeerId },
+
+    /// The peer is banned, or the peer has banned us.
+    #[error("peer is banned")]
+    Banned,
+
+    /// The peer refused the connection because it's in drain mode, see
+    /// [`Network::enter_drain_mode`](crate::Network::enter_drain_mode).
+    #[error("peer is in drain mode")]
+    DrainMode,
+
+    /// The connection failed for any other reason, described by the contained message.
+    #[error("{0}")]
+    Transport(String),
+}
+
+impl ConnectError {
+    /// Classify an error reported by quinn while establishing a connection.
+    pub(crate) fn from_connection_error(error: &quinn::ConnectionError) -> Self {
+        use crate::connection::close_code;
+
+        // Errors raised by the TLS stack are reported with codes in the crypto error range
+        let is_crypto =
+            |code: quinn_proto::TransportErrorCode| (0x100..0x200).contains(&u64::from(code));
+
+        match error {
+            quinn::ConnectionError::TimedOut => ConnectError::Timeout,
+            quinn::ConnectionError::ApplicationClosed(close) => {
+                let code = close.error_code.into_inner();
+                if code == u64::from(close_code::BANNED) {
+                    ConnectError::Banned
+                } else if code == u64::from(close_code::DRAINING) {
+                    ConnectError::DrainMode
+                } else if code == u64::from(close_code::VERSION_MISMATCH) {
+                    ConnectError::VersionMismatch
+                } else {
+                    ConnectError::Refused(String::from_utf8_lossy(&close.reason).into_owned())
+                }
+            }
+            quinn::ConnectionError::TransportError(e) if is_crypto(e.code) => {
+                ConnectError::TlsError(error.to_string())
+            }
+            quinn::ConnectionError::ConnectionClosed(close) if is_crypto(close.error_code) => {
+                ConnectError::TlsError(error.to_string())
+            }
+            _ => ConnectError::Transport(error.to_string()),
+        }
+    }
+
+    /// Make the [`ConnectError`] describing `error`, a failure to establish an outbound
+    /// connection, available via downcasting, unless it already is.
+    pub(crate) fn attach(error: anyhow::Error) -> anyhow::Error {
+        if error.is::<ConnectError>() {
+            return error;
+        }
+
+        let connect_error =
+            if error.is::<tokio::time::error::Elapsed>() || error.is::<TimeoutExpired>() {
+                ConnectError::Timeout
+            } else if let Some(e) = find_connection_error(&error) {
+                ConnectError::from_connection_error(e)
+            } else if error.is::<PeerNotAllowed>() {
+                ConnectError::TlsError(error.to_string())
+            } else if error.is::<SelfConnection>() {
+                ConnectError::Refused(error.to_string())
+            } else {
+                ConnectError::Transport(error.to_string())
+            };
+        error.context(connect_error)
+    }
+}
+
+/// Find the error which caused the connection to be lost, if that's why a connection or stream
+/// operation failed.
+fn find_connection_error(error: &anyhow::Error) -> Option<&quinn::ConnectionError> {
+    // Stream errors are surfaced as `std::io::Error`s when reading or writing via tokio's traits
+    let io_error = error
+        .downcast_ref::<std::io::Error>()
+        .and_then(std::io::Error::get_ref);
+
+    let read_error = error
+        .downcast_ref::<quinn::ReadError>()
+        .or_else(|| match error.downcast_ref::<quinn::ReadToEndError>() {
+            Some(quinn::ReadToEndError::Read(e)) => Some(e),
+            _ => None,
+        })
+        .or_else(|| io_error.and_then(|e| e.downcast_ref::<quinn::ReadError>()));
+    let write_error = error
+        .downcast_ref::<quinn::WriteError>()
+        .or_else(|| io_error.and_then(|e| e.downcast_ref::<quinn::WriteError>()));
+
+    match (read_error, write_error) {
+        (Some(quinn::ReadError::ConnectionLost(e)), _)
+        | (_, Some(quinn::WriteError::ConnectionLost(e))) => Some(e),
+        _ => error.downcast_ref::<quinn::ConnectionError>(),
+    }
+}
+
+/// Error returned when performing an RPC via [`Network::rpc`](crate::Network::rpc) or
+/// [`Peer::rpc`](crate::Peer::rpc).
+///
+/// Any other error, for example one produced by an outbound request layer, is preserved via the
+/// [`RpcError::Other`] variant.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum RpcError {
+    /// There is no active connection with the peer.
+    #[error("not connected to peer {0}")]
+    NotConnected(PeerId),
+
+    /// The connection with the peer was lost while performing the RPC.
+    #[error("connection lost")]
+    ConnectionLost,
+
+    /// The RPC didn't complete within the configured timeout.
+    #[error("rpc timed out")]
+    Timeout,
+
+    /// The response from the peer could not be decoded.
+    #[error("unable to decode response: {0}")]
+    WireDecode(String),
+
+    /// The peer closed the stream before sending a complete response.
+    #[error("stream closed by remote")]
+    RemoteClosed,
+
+    /// The RPC was cancelled via the [`AbortHandle`](futures::future::AbortHandle) returned by
+    /// [`Peer::rpc_abortable`](crate::Peer::rpc_abortable).
+    #[error("rpc was cancelled")]
+    Cancelled,
+
+    /// Opening another stream to the peer would exceed
+    /// [`Config::max_concurrent_outbound_streams_per_peer`](crate::Config::max_concurrent_outbound_streams_per_peer).
+    #[error("too many concurrent outbound streams to peer {0}")]
+    TooManyStreams(PeerId),
+
+    /// The peer's handler reset the response stream with an application error `code`, see
+    /// [`ApplicationErrorCode`](crate::types::response::ApplicationErrorCode).
+    #[error("rpc failed with application error code {code}")]
+    Application { code: u64 },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl RpcError {
+    /// Classify an error encountered while reading or writing from a stream.
+    pub(crate) fn from_stream_error(error: anyhow::Error) -> Self {
+        // quinn maps stream resets to `ConnectionReset` and connection failures to `NotConnected`
+        // when converting its `ReadError` and `WriteError` types into `std::io::Error`s.
+        let io_error = error.downcast_ref::<std::io::Error>();
+
+        // Streams reset with a nonzero code were reset deliberately by the peer's handler
+        if let Some(quinn::ReadError::Reset(code)) = io_error
+            .and_then(std::io::Error::get_ref)
+            .and_then(|e| e.downcast_ref::<quinn::ReadError>())
+        {
+            if code.into_inner() != 0 {
+                return RpcError::Application {
+                    code: code.into_inner(),
+                };
+            }
+        }
+
+        match io_error.map(std::io::Error::kind) {
+            Some(std::io::ErrorKind::ConnectionReset) => RpcError::RemoteClosed,
+            Some(std::io::ErrorKind::NotConnected) => RpcError::ConnectionLost,
+            // The peer finished the stream before sending a complete response
+            Some(std::io::ErrorKind::UnexpectedEof) => RpcError::RemoteClosed,
+            _ => RpcError::WireDecode(error.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<RpcError>() {
+            Ok(error) => error,
+            Err(error) if error.is::<TimeoutExpired>() => RpcError::Timeout,
+            Err(error) => RpcError::Other(error),
+        }
+    }
+}
+
+impl From<quinn::ConnectionError> for RpcError {
+    fn from(_: quinn::ConnectionError) -> Self {
+        RpcError::ConnectionLost
+    }
+}
+
+impl From<quinn::WriteError> for RpcError {
+    fn from(error: quinn::WriteError) -> Self {
+        match error {
+            quinn::WriteError::Stopped(_) => RpcError::RemoteClosed,
+            _ => RpcError::ConnectionLost,
+        }
+    }
+}
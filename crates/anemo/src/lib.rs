@@ -1,20 +1,34 @@
+mod bandwidth;
 mod config;
 mod connection;
 mod crypto;
 mod endpoint;
 mod error;
+mod metrics;
 pub mod middleware;
 mod network;
 mod routing;
 pub mod rpc;
 pub mod types;
 
-pub use config::{Config, QuicConfig};
-pub use error::{Error, Result};
-pub use network::{Builder, KnownPeers, Network, NetworkRef, Peer};
-pub use routing::Router;
+pub use config::{
+    CompressionConfig, Config, CongestionController, DatagramOverflowPolicy, QuicConfig,
+    ReconnectConfig, RequestScheduling,
+};
+pub use error::{
+    ConnectError, Error, PeerNotAllowed, Result, RpcError, SelfConnection, TimeoutExpired,
+};
+pub use metrics::{ConnectionObserver, RpcOutcome};
+pub use network::{
+    Builder, DatagramReceiver, InboundRawStream, KnownPeers, Network, NetworkHandle, NetworkRef,
+    OrderedSender, Peer, PeerResolver, RawRecvStream, RawSendStream, RetryPolicy, StreamReceiver,
+    StreamSender, Subscription, SubscriptionEvent,
+};
+pub use routing::{RouteOpts, Router};
 #[doc(inline)]
-pub use types::{request::Request, response::Response, ConnectionOrigin, Direction, PeerId};
+pub use types::{
+    request::Request, response::Response, ConnectionOrigin, Direction, ParsePeerIdError, PeerId,
+};
 
 pub use async_trait::async_trait;
 
@@ -0,0 +1,328 @@
+//! Instrumentation of a [`Network`](crate::Network).
+//!
+//! When the `metrics` feature is enabled, metrics are recorded to a [`prometheus::Registry`]
+//! which can be accessed via [`Network::registry`](crate::Network::registry). Otherwise all
+//! recording is a no-op.
+//!
+//! Independently of the feature, a [`ConnectionObserver`] can be registered via
+//! [`Builder::connection_observer`](crate::Builder::connection_observer) to export the same events
+//! to any other metrics backend.
+
+#[cfg(not(feature = "metrics"))]
+use self::noop::Recorder;
+#[cfg(feature = "metrics")]
+use self::prometheus_metrics::Recorder;
+use crate::{types::DisconnectReason, ConnectionOrigin, Direction, PeerId};
+use std::{sync::Arc, time::Duration};
+
+/// Observes connection and RPC events, e.g. to export metrics to a backend of the user's choice.
+///
+/// Register an observer via [`Builder::connection_observer`](crate::Builder::connection_observer).
+/// All methods do nothing by default so that only the events of interest need to be implemented.
+///
+/// Callbacks are invoked synchronously from the network's tasks, in some cases while internal
+/// locks are held, so they must be cheap and must not block or call back into the
+/// [`Network`](crate::Network). Any expensive work should be handed off to another task.
+pub trait ConnectionObserver: Send + Sync + 'static {
+    /// A connection was established with `peer_id`.
+    fn on_established(&self, _peer_id: PeerId, _origin: ConnectionOrigin) {}
+
+    /// The connection with `peer_id` was closed or lost.
+    fn on_closed(&self, _peer_id: PeerId, _reason: &DisconnectReason) {}
+
+    /// An RPC on `route` with `peer_id` completed with `outcome` after `latency`. `direction` is
+    /// [`Direction::Outbound`] for RPCs made by this node and [`Direction::Inbound`] for RPCs
+    /// handled by it.
+    fn on_rpc(
+        &self,
+        _peer_id: PeerId,
+        _direction: Direction,
+        _route: &str,
+        _latency: Duration,
+        _outcome: RpcOutcome,
+    ) {
+    }
+}
+
+/// The outcome of a completed RPC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RpcOutcome {
+    /// A response with a successful status was received or sent.
+    Success,
+    /// The RPC failed, or a response with an error status was received or sent.
+    Failure,
+    /// The RPC didn't complete within its timeout.
+    Timeout,
+}
+
+impl RpcOutcome {
+    pub(crate) fn from_outbound_result(
+        result: &Result<crate::Response<bytes::Bytes>, crate::Error>,
+    ) -> Self {
+        match result {
+            Ok(response) => Self::from_response(response),
+            Err(e) if e.is::<crate::TimeoutExpired>() => Self::Timeout,
+            Err(e)
+                if matches!(
+                    e.downcast_ref::<crate::RpcError>(),
+                    Some(crate::RpcError::Timeout)
+                ) =>
+            {
+                Self::Timeout
+            }
+            Err(_) => Self::Failure,
+        }
+    }
+
+    pub(crate) fn from_response(response: &crate::Response<bytes::Bytes>) -> Self {
+        if response.status().is_success() {
+            Self::Success
+        } else {
+            Self::Failure
+        }
+    }
+}
+
+/// Records metrics and notifies the [`ConnectionObserver`], if one was registered, of connection
+/// and RPC events.
+#[derive(Clone, Default)]
+pub(crate) struct Metrics {
+    recorder: Recorder,
+    observer: Option<Arc<dyn ConnectionObserver>>,
+}
+
+impl Metrics {
+    /// Register all metrics with `registry`, returning an error if any have already been
+    /// registered.
+    #[cfg(feature = "metrics")]
+    pub fn new(registry: prometheus::Registry) -> crate::Result<Self> {
+        Ok(Self {
+            recorder: Recorder::new(registry)?,
+            observer: None,
+        })
+    }
+
+    pub fn with_observer(mut self, observer: Option<Arc<dyn ConnectionObserver>>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn registry(&self) -> &prometheus::Registry {
+        self.recorder.registry()
+    }
+
+    pub fn connection_established(
+        &self,
+        peer_id: PeerId,
+        origin: ConnectionOrigin,
+        active_peers: usize,
+    ) {
+        self.recorder.connection_established(active_peers);
+        if let Some(observer) = &self.observer {
+            observer.on_established(peer_id, origin);
+        }
+    }
+
+    pub fn connection_lost(&self, peer_id: PeerId, reason: &DisconnectReason, active_peers: usize) {
+        self.recorder.connection_lost(active_peers);
+        if let Some(observer) = &self.observer {
+            observer.on_closed(peer_id, reason);
+        }
+    }
+
+    pub fn rpc_completed(
+        &self,
+        peer_id: PeerId,
+        direction: Direction,
+        route: &str,
+        outcome: RpcOutcome,
+        latency: Duration,
+    ) {
+        self.recorder.rpc_completed(direction, outcome, latency);
+        if let Some(observer) = &self.observer {
+            observer.on_rpc(peer_id, direction, route, latency, outcome);
+        }
+    }
+
+    pub fn bytes_sent(&self, bytes: usize) {
+        self.recorder.bytes_sent(bytes)
+    }
+
+    pub fn bytes_received(&self, bytes: usize) {
+        self.recorder.bytes_received(bytes)
+    }
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics")
+            .field("recorder", &self.recorder)
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod noop {
+    use super::RpcOutcome;
+    use crate::Direction;
+    use std::time::Duration;
+
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct Recorder;
+
+    impl Recorder {
+        pub fn connection_established(&self, _active_peers: usize) {}
+
+        pub fn connection_lost(&self, _active_peers: usize) {}
+
+        pub fn rpc_completed(
+            &self,
+            _direction: Direction,
+            _outcome: RpcOutcome,
+            _latency: Duration,
+        ) {
+        }
+
+        pub fn bytes_sent(&self, _bytes: usize) {}
+
+        pub fn bytes_received(&self, _bytes: usize) {}
+    }
+}
+
+#[cfg(feature = "metrics")]
+mod prometheus_metrics {
+    use super::RpcOutcome;
+    use crate::{Direction, Result};
+    use prometheus::{
+        register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
+        register_int_counter_with_registry, register_int_gauge_with_registry, HistogramVec,
+        IntCounter, IntCounterVec, IntGauge, Registry,
+    };
+    use std::{sync::Arc, time::Duration};
+
+    #[derive(Clone)]
+    pub(crate) struct Recorder(Arc<RecorderInner>);
+
+    struct RecorderInner {
+        registry: Registry,
+        connections_established: IntCounter,
+        connections_lost: IntCounter,
+        active_peers: IntGauge,
+        rpcs: IntCounterVec,
+        rpc_latency: HistogramVec,
+        bytes_sent: IntCounter,
+        bytes_received: IntCounter,
+    }
+
+    impl Recorder {
+        /// Register all metrics with `registry`.
+        ///
+        /// Returns an error if any of the metrics have already been registered, e.g. if
+        /// `registry` is shared with another [`Network`](crate::Network).
+        pub fn new(registry: Registry) -> Result<Self> {
+            let connections_established = register_int_counter_with_registry!(
+                "anemo_connections_established_total",
+                "Number of connections established with peers",
+                registry
+            )?;
+            let connections_lost = register_int_counter_with_registry!(
+                "anemo_connections_lost_total",
+                "Number of connections with peers which were closed or lost",
+                registry
+            )?;
+            let active_peers = register_int_gauge_with_registry!(
+                "anemo_active_peers",
+                "Number of peers with an active connection",
+                registry
+            )?;
+            let rpcs = register_int_counter_vec_with_registry!(
+                "anemo_rpcs_total",
+                "Number of completed RPCs by direction and outcome",
+                &["direction", "outcome"],
+                registry
+            )?;
+            let rpc_latency = register_histogram_vec_with_registry!(
+                "anemo_rpc_latency_seconds",
+                "Latency of completed RPCs by direction",
+                &["direction"],
+                registry
+            )?;
+            let bytes_sent = register_int_counter_with_registry!(
+                "anemo_bytes_sent_total",
+                "Number of request and response body bytes sent to peers",
+                registry
+            )?;
+            let bytes_received = register_int_counter_with_registry!(
+                "anemo_bytes_received_total",
+                "Number of request and response body bytes received from peers",
+                registry
+            )?;
+
+            Ok(Self(Arc::new(RecorderInner {
+                registry,
+                connections_established,
+                connections_lost,
+                active_peers,
+                rpcs,
+                rpc_latency,
+                bytes_sent,
+                bytes_received,
+            })))
+        }
+
+        pub fn registry(&self) -> &Registry {
+            &self.0.registry
+        }
+
+        pub fn connection_established(&self, active_peers: usize) {
+            self.0.connections_established.inc();
+            self.0.active_peers.set(active_peers as i64);
+        }
+
+        pub fn connection_lost(&self, active_peers: usize) {
+            self.0.connections_lost.inc();
+            self.0.active_peers.set(active_peers as i64);
+        }
+
+        pub fn rpc_completed(&self, direction: Direction, outcome: RpcOutcome, latency: Duration) {
+            let direction = match direction {
+                Direction::Inbound => "inbound",
+                Direction::Outbound => "outbound",
+            };
+            let outcome = match outcome {
+                RpcOutcome::Success => "success",
+                RpcOutcome::Failure => "failure",
+                RpcOutcome::Timeout => "timeout",
+            };
+
+            self.0.rpcs.with_label_values(&[direction, outcome]).inc();
+            self.0
+                .rpc_latency
+                .with_label_values(&[direction])
+                .observe(latency.as_secs_f64());
+        }
+
+        pub fn bytes_sent(&self, bytes: usize) {
+            self.0.bytes_sent.inc_by(bytes as u64);
+        }
+
+        pub fn bytes_received(&self, bytes: usize) {
+            self.0.bytes_received.inc_by(bytes as u64);
+        }
+    }
+
+    impl Default for Recorder {
+        fn default() -> Self {
+            Self::new(Registry::new()).expect("metrics are only registered once")
+        }
+    }
+
+    impl std::fmt::Debug for Recorder {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Recorder").finish_non_exhaustive()
+        }
+    }
+}
@@ -28,7 +28,7 @@ pub(crate) fn duration_to_timeout(duration: Duration) -> String {
 
 /// Error returned if a request didn't complete within the configured timeout.
 #[derive(Debug)]
-pub(crate) struct TimeoutExpired(());
+pub struct TimeoutExpired(pub(crate) ());
 
 impl fmt::Display for TimeoutExpired {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
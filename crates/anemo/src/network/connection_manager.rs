@@ -1,28 +1,71 @@
-use super::request_handler::InboundRequestHandler;
+use super::{
+    datagram::DatagramSubscribers, push::PushSubscribers, raw_stream::RawStreamSubscribers,
+    request_handler::InboundRequestHandler, scheduler::RequestScheduler, stream::StreamHandler,
+    PeerResolver,
+};
 use crate::{
-    config::Config,
-    connection::Connection,
+    config::{Config, ReconnectConfig},
+    connection::{close_code, Connection},
     endpoint::{Connecting, Endpoint},
-    types::{Address, DisconnectReason, PeerAffinity, PeerEvent, PeerInfo},
-    ConnectionOrigin, PeerId, Request, Response, Result,
+    metrics::Metrics,
+    types::{
+        AcceptError, Address, DisconnectCause, DisconnectReason, EndpointStats, PeerAffinity,
+        PeerEvent, PeerInfo,
+    },
+    ConnectError, ConnectionOrigin, PeerId, Request, Response, Result, SelfConnection,
 };
 use bytes::Bytes;
+use futures::future::BoxFuture;
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     convert::Infallible,
-    sync::{Arc, RwLock},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
 };
 use tokio::{
-    sync::{broadcast, mpsc, oneshot},
+    sync::{broadcast, mpsc, oneshot, watch},
     task::JoinSet,
 };
 use tower::util::BoxCloneService;
 use tracing::{debug, info, instrument, trace};
 
+/// Predicate deciding which [`PeerEvent`]s are delivered to a filtered subscriber, see
+/// [`Network::subscribe_filtered`](super::Network::subscribe_filtered).
+pub(crate) type PeerEventFilter = Box<dyn Fn(&PeerEvent) -> bool + Send + Sync>;
+
+/// Type-erased hook run for each newly established connection, see
+/// [`Builder::on_connect`](super::Builder::on_connect).
+pub(crate) type ConnectHook =
+    Arc<dyn Fn(PeerId, SocketAddr) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// A caller waiting on the result of a dial which was coalesced with one already in progress.
+type DialWaiter = oneshot::Sender<Result<PeerId>>;
+
 #[derive(Debug)]
 pub enum ConnectionManagerRequest {
     ConnectRequest(Address, Option<PeerId>, oneshot::Sender<Result<PeerId>>),
+    ReconnectKnown,
     Shutdown(oneshot::Sender<()>),
+    GracefulShutdown(Duration, oneshot::Sender<()>),
+}
+
+struct QueuedDial {
+    address: Address,
+    peer_id: Option<PeerId>,
+    oneshot: oneshot::Sender<Result<PeerId>>,
+}
+
+/// The outcome of asking the [`PeerResolver`] for the addresses of a known peer which has none.
+struct ResolvedPeer {
+    peer_id: PeerId,
+    addresses: Result<Vec<SocketAddr>>,
+    /// Number of previous attempts to dial the peer, used to pick which address to dial
+    attempts: usize,
+    oneshot: oneshot::Sender<Result<PeerId>>,
 }
 
 struct ConnectingOutput {
@@ -30,6 +73,10 @@ struct ConnectingOutput {
     maybe_oneshot: Option<oneshot::Sender<Result<PeerId>>>,
     target_address: Option<Address>,
     target_peer_id: Option<PeerId>,
+    /// The remote address of an inbound connection
+    accepted_from: Option<SocketAddr>,
+    /// Whether an outbound dial was abandoned because its requester stopped waiting on it
+    cancelled: bool,
 }
 
 /// The active service responsible establishing new inbound and outbound connections.
@@ -38,6 +85,9 @@ pub(crate) struct ConnectionManager {
 
     endpoint: Arc<Endpoint>,
 
+    /// Endpoints bound to any additional addresses, sharing the same identity as `endpoint`.
+    additional_endpoints: Vec<Arc<Endpoint>>,
+
     /// Channel to receive external requests/commands for the connection manager to perform.
     ///
     /// The ConnectionManager will graceful shutdown once this channel is closed.
@@ -52,41 +102,118 @@ pub(crate) struct ConnectionManager {
     /// A map of all the inflight attempts to establish outbound connections started internally due
     /// to a peer being configured as a KnownPeer.
     pending_dials: HashMap<PeerId, oneshot::Receiver<Result<PeerId>>>,
+    /// Callers waiting on the result of an in-progress dial to the same address and peer, keyed
+    /// by the dial's target. Only one handshake is run per target at a time.
+    coalesced_dials: HashMap<(Address, Option<PeerId>), Vec<DialWaiter>>,
     dial_backoff_states: HashMap<PeerId, DialBackoffState>,
+    /// Known peers which we have been connected with before, so that dialing them again is a
+    /// reconnection which backs off according to [`Config::reconnect`].
+    previously_connected: HashSet<PeerId>,
+
+    /// Known peers whose addresses are being resolved before they can be dialed.
+    pending_resolutions: JoinSet<ResolvedPeer>,
+
+    /// Outbound dials waiting on the outbound dial rate limit before they can be started.
+    queued_dials: VecDeque<QueuedDial>,
+    /// The earliest point in time at which the next rate limited dial can be started.
+    next_dial_at: std::time::Instant,
 
     active_peers: ActivePeers,
     known_peers: KnownPeers,
+    banned_peers: BannedPeers,
 
     service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+    stream_handler: Option<StreamHandler>,
+    connect_hook: Option<ConnectHook>,
+    /// Routes advertised to peers during the handshake.
+    capabilities: Arc<[String]>,
+    peer_resolver: Option<Arc<dyn PeerResolver>>,
+    datagram_subscribers: DatagramSubscribers,
+    push_subscribers: PushSubscribers,
+    raw_stream_subscribers: RawStreamSubscribers,
+    /// Shared by all connection handlers to bound the number of inbound requests processed
+    /// concurrently, if `max-concurrent-requests` is configured.
+    request_scheduler: Option<RequestScheduler>,
+    endpoint_counters: Arc<EndpointCounters>,
+    /// Set while the network is in drain mode, during which new inbound connections are refused.
+    drain_mode: Arc<AtomicBool>,
+
+    /// Signals connection handlers to stop accepting new requests and to close their connection
+    /// once all in-flight requests have completed.
+    draining: watch::Sender<bool>,
+    draining_receiver: watch::Receiver<bool>,
+
+    metrics: Metrics,
 }
 
 impl Drop for ConnectionManager {
     fn drop(&mut self) {
-        self.endpoint.close()
+        for endpoint in self.endpoints() {
+            endpoint.close()
+        }
     }
 }
 
 impl ConnectionManager {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<Config>,
         endpoint: Arc<Endpoint>,
+        additional_endpoints: Vec<Arc<Endpoint>>,
         active_peers: ActivePeers,
         known_peers: KnownPeers,
+        banned_peers: BannedPeers,
         service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+        stream_handler: Option<StreamHandler>,
+        connect_hook: Option<ConnectHook>,
+        capabilities: Arc<[String]>,
+        peer_resolver: Option<Arc<dyn PeerResolver>>,
+        datagram_subscribers: DatagramSubscribers,
+        push_subscribers: PushSubscribers,
+        raw_stream_subscribers: RawStreamSubscribers,
+        endpoint_counters: Arc<EndpointCounters>,
+        drain_mode: Arc<AtomicBool>,
+        metrics: Metrics,
     ) -> (Self, mpsc::Sender<ConnectionManagerRequest>) {
         let (sender, receiver) = mpsc::channel(config.connection_manager_channel_capacity());
+        let (draining, draining_receiver) = watch::channel(false);
+        let request_scheduler = config
+            .max_concurrent_requests()
+            .map(|max_concurrent_requests| {
+                RequestScheduler::new(max_concurrent_requests, config.request_scheduling())
+            });
         (
             Self {
                 config,
                 endpoint,
+                additional_endpoints,
                 mailbox: receiver,
                 pending_connections: JoinSet::new(),
                 connection_handlers: JoinSet::new(),
                 pending_dials: HashMap::default(),
+                coalesced_dials: HashMap::default(),
                 dial_backoff_states: HashMap::default(),
+                previously_connected: HashSet::default(),
+                pending_resolutions: JoinSet::new(),
+                queued_dials: VecDeque::new(),
+                next_dial_at: std::time::Instant::now(),
                 active_peers,
                 known_peers,
+                banned_peers,
                 service,
+                stream_handler,
+                connect_hook,
+                capabilities,
+                peer_resolver,
+                datagram_subscribers,
+                push_subscribers,
+                raw_stream_subscribers,
+                request_scheduler,
+                endpoint_counters,
+                drain_mode,
+                draining,
+                draining_receiver,
+                metrics,
             },
             sender,
         )
@@ -116,12 +243,24 @@ impl ConnectionManager {
             tokio::time::interval(self.config.connectivity_check_interval() + jitter);
 
         let mut shutdown_notifier = None;
+        let mut grace_period = None;
 
         loop {
+            let backoff_deadline = self.next_dial_backoff_deadline();
+            let queued_dial_deadline = (!self.queued_dials.is_empty()).then_some(self.next_dial_at);
+
             tokio::select! {
                 now = interval.tick() => {
                     self.handle_connectivity_check(now.into_std());
                 }
+                // Retry dialing peers as soon as their backoff has elapsed instead of waiting for
+                // the next connectivity check interval
+                _ = sleep_until(backoff_deadline), if backoff_deadline.is_some() => {
+                    self.handle_connectivity_check(std::time::Instant::now());
+                }
+                _ = sleep_until(queued_dial_deadline), if queued_dial_deadline.is_some() => {
+                    self.process_queued_dials(std::time::Instant::now());
+                }
                 maybe_request = self.mailbox.recv() => {
                     // Once all handles to the ConnectionManager's mailbox have been dropped this
                     // will yield `None` and we can break out of the event loop and terminate the
@@ -136,10 +275,20 @@ impl ConnectionManager {
                         ConnectionManagerRequest::ConnectRequest(address, peer_id, oneshot) => {
                             self.handle_connect_request(address, peer_id, oneshot);
                         }
+                        ConnectionManagerRequest::ReconnectKnown => {
+                            // Forget any backoff so that all eligible peers are dialed right away
+                            self.dial_backoff_states.clear();
+                            self.handle_connectivity_check(std::time::Instant::now());
+                        }
                         ConnectionManagerRequest::Shutdown(oneshot) => {
                             shutdown_notifier = Some(oneshot);
                             break;
                         }
+                        ConnectionManagerRequest::GracefulShutdown(duration, oneshot) => {
+                            shutdown_notifier = Some(oneshot);
+                            grace_period = Some(duration);
+                            break;
+                        }
                     }
                 }
                 connecting = self.endpoint.accept() => {
@@ -147,16 +296,32 @@ impl ConnectionManager {
                         self.handle_incoming(connecting);
                     }
                 },
+                connecting = accept_any(&self.additional_endpoints) => {
+                    if let Some(connecting) = connecting {
+                        self.handle_incoming(connecting);
+                    }
+                },
                 Some(connecting_output) = self.pending_connections.join_next() => {
                     self.handle_connecting_result(connecting_output.unwrap());
                 },
+                Some(resolved_peer) = self.pending_resolutions.join_next() => {
+                    self.handle_resolved_peer(resolved_peer.unwrap());
+                },
                 Some(connection_handler_output) = self.connection_handlers.join_next() => {
                     // If a task panics, just propagate it
                     connection_handler_output.unwrap();
+
+                    // A connection was lost, immediately try to re-establish connections with any
+                    // peers we should maintain a connection with
+                    self.handle_connectivity_check(std::time::Instant::now());
                 },
             }
         }
 
+        if let Some(grace_period) = grace_period {
+            self.drain(grace_period).await;
+        }
+
         self.shutdown().await;
 
         if let Some(sender) = shutdown_notifier {
@@ -166,12 +331,41 @@ impl ConnectionManager {
         info!("ConnectionManager ended");
     }
 
+    // Stop accepting new connections and requests and wait, up to `grace_period`, for all
+    // connection handlers to finish processing their in-flight requests.
+    async fn drain(&mut self, grace_period: Duration) {
+        debug!("draining connections");
+
+        for endpoint in self.endpoints() {
+            endpoint.reject_new_connections();
+        }
+        // This can't fail since we hold on to a receiver for the lifetime of the ConnectionManager
+        let _ = self.draining.send(true);
+
+        let drained = tokio::time::timeout(grace_period, async {
+            while let Some(connection_handler_output) = self.connection_handlers.join_next().await {
+                // If a task panics, just propagate it
+                connection_handler_output.unwrap();
+            }
+        })
+        .await;
+
+        if drained.is_err() {
+            debug!(
+                "grace period of {}s elapsed before all in-flight requests completed",
+                grace_period.as_secs_f64()
+            );
+        }
+    }
+
     // Proceed through a graceful shutdown process that will leave the underlying socket
     // immediately re-bindable.
     async fn shutdown(mut self) {
-        // Close the quinn endpoint. This starts the process of gracefully shutting down all
+        // Close the quinn endpoints. This starts the process of gracefully shutting down all
         // connections, notifying the remote side of the endpoint's closure.
-        self.endpoint.close();
+        for endpoint in self.endpoints() {
+            endpoint.close();
+        }
 
         // Terminate any in-progress pending connections
         self.pending_connections.shutdown().await;
@@ -184,23 +378,36 @@ impl ConnectionManager {
             "ActivePeers should be empty after all connection handlers have terminated"
         );
 
-        // wait for the endpoint to be idle
-        self.endpoint
-            .wait_idle(self.config.shutdown_idle_timeout())
-            .await;
+        // wait for the endpoints to be idle
+        let shutdown_idle_timeout = self.config.shutdown_idle_timeout();
+        futures::future::join_all(
+            self.endpoints()
+                .map(|endpoint| endpoint.wait_idle(shutdown_idle_timeout)),
+        )
+        .await;
 
-        // This is a small hack in order to ensure that the underlying socket we're bound to is
+        // This is a small hack in order to ensure that the underlying sockets we're bound to are
         // dropped and immediately available to be rebound to once this function exits.
         // In essence we construct a new UdpSocket, bound on some ephemeral localhost port, and
         // swap it in for the socket the endpoint is currently bound to, causing it to be dropped
         // and freed.
-        let socket = std::net::UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
-        self.endpoint.rebind(socket).unwrap();
+        for endpoint in self.endpoints() {
+            let socket = std::net::UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+            endpoint.rebind(socket).unwrap();
+        }
+    }
+
+    /// Iterate over all of the endpoints this ConnectionManager is responsible for.
+    fn endpoints(&self) -> impl Iterator<Item = &Arc<Endpoint>> {
+        std::iter::once(&self.endpoint).chain(&self.additional_endpoints)
     }
 
     /// This method adds an established connection with a peer to the map of active peers.
     /// It is also starting a new task to handle the incoming messages for this connection.
     fn add_peer(&mut self, new_connection: Connection) {
+        new_connection
+            .set_outbound_bandwidth_limit(self.config.max_outbound_bytes_per_second_per_peer());
+        self.previously_connected.insert(new_connection.peer_id());
         if let Some(new_connection) = self
             .active_peers
             .add(&self.endpoint.peer_id(), new_connection)
@@ -209,7 +416,15 @@ impl ConnectionManager {
                 self.config.clone(),
                 new_connection,
                 self.service.clone(),
+                self.stream_handler.clone(),
                 self.active_peers.clone(),
+                self.datagram_subscribers.clone(),
+                self.push_subscribers.clone(),
+                self.raw_stream_subscribers.clone(),
+                self.request_scheduler.clone(),
+                self.endpoint_counters.clone(),
+                self.draining_receiver.clone(),
+                self.metrics.clone(),
             );
 
             // TODO think about removing the need to pass in the active set of peers to the
@@ -224,7 +439,11 @@ impl ConnectionManager {
         peer_id: Option<PeerId>,
         oneshot: oneshot::Sender<Result<PeerId>>,
     ) {
-        self.dial_peer(address, peer_id, oneshot);
+        if self.config.rate_limit_explicit_connects() {
+            self.queue_dial(address, peer_id, oneshot);
+        } else {
+            self.dial_peer(address, peer_id, oneshot);
+        }
     }
 
     fn handle_incoming(&mut self, connecting: Connecting) {
@@ -232,28 +451,89 @@ impl ConnectionManager {
 
         self.pending_connections.spawn(Self::handle_incoming_task(
             connecting,
+            self.endpoint.peer_id(),
             self.config.clone(),
             self.active_peers.clone(),
             self.known_peers.clone(),
+            self.banned_peers.clone(),
+            self.connect_hook.clone(),
+            self.capabilities.clone(),
+            self.drain_mode.clone(),
         ));
     }
 
+    #[instrument(
+        name = "accept",
+        level = "debug",
+        skip_all,
+        fields(remote_addr = %connecting.remote_address(), peer_id = tracing::field::Empty)
+    )]
+    #[allow(clippy::too_many_arguments)]
     async fn handle_incoming_task(
         connecting: Connecting,
+        own_peer_id: PeerId,
         config: Arc<Config>,
         active_peers: ActivePeers,
         known_peers: KnownPeers,
+        banned_peers: BannedPeers,
+        connect_hook: Option<ConnectHook>,
+        capabilities: Arc<[String]>,
+        drain_mode: Arc<AtomicBool>,
     ) -> ConnectingOutput {
+        let remote_address = connecting.remote_address();
         let fut = async {
             let connection = connecting.await?;
+            tracing::Span::current()
+                .record("peer_id", tracing::field::display(connection.peer_id()));
+
+            reject_self_connection(own_peer_id, &connection)?;
+
+            if drain_mode.load(Ordering::Relaxed) {
+                connection.close_with_reason(close_code::DRAINING, b"draining");
+                return Err(anyhow::anyhow!(
+                    "rejecting connection from peer {} while in drain mode",
+                    connection.peer_id()
+                ));
+            }
+
+            if banned_peers.is_banned(&connection.peer_id()) {
+                connection.close_with_reason(close_code::BANNED, b"peer is banned");
+                return Err(anyhow::anyhow!(
+                    "rejecting connection from banned peer {}",
+                    connection.peer_id()
+                ));
+            }
+
+            // Run the connect hook before checking connection limits so that a rejected
+            // connection never causes another peer to be evicted
+            run_connect_hook(connect_hook.as_ref(), &connection).await?;
+
+            // Likewise exchange hellos first so that a peer refused due to its version or network
+            // id never causes another peer to be evicted. The dialer only considers the
+            // connection established once we accept the handshake below.
+            let handshake =
+                super::wire::accept_handshake(connection, &config, &capabilities).await?;
+            let connection = handshake.connection();
 
             // TODO close the connection explicitly with a reason once we have machine
             // readable errors. See https://github.com/MystenLabs/anemo/issues/13 for more info.
             match known_peers.get(&connection.peer_id()) {
-                Some(PeerInfo { affinity, .. }) if matches!(affinity, PeerAffinity::High) => {
-                    // Do nothing, let the connection through
+                Some(PeerInfo {
+                    affinity: PeerAffinity::High,
+                    ..
+                }) => {
+                    // Let the connection through, evicting a peer with a lower affinity if we're
+                    // at the connection limit
+                    if let Some(limit) = active_peers.max_peers() {
+                        if active_peers.len() >= limit {
+                            active_peers.evict_one(&known_peers);
+                        }
+                    }
                 }
-                Some(PeerInfo { affinity, .. }) if matches!(affinity, PeerAffinity::Never) => {
+                Some(PeerInfo {
+                    affinity: PeerAffinity::Never,
+                    ..
+                }) => {
                     return Err(anyhow::anyhow!(
                         "rejecting connection from peer {} due to having PeerAffinity::Never",
                         connection.peer_id()
@@ -261,12 +541,31 @@ impl ConnectionManager {
                 }
                 // Check connection Limits
                 _ => {
-                    if let Some(limit) = config.max_concurrent_connections() {
+                    if let Some(limit) = config.max_connections_per_ip() {
+                        let ip = connection.remote_address().ip();
+                        if active_peers.inbound_connections_from(ip, &known_peers) >= limit {
+                            connection.close_with_reason(
+                                close_code::CONNECTION_LIMIT,
+                                b"connection limit for address reached",
+                            );
+                            return Err(anyhow::anyhow!(
+                                "dropping connection from peer {} due to the per-ip connection \
+                                 limit for {ip}",
+                                connection.peer_id()
+                            ));
+                        }
+                    }
+
+                    if let Some(limit) = active_peers.max_peers() {
                         // We've hit the limit
                         // TODO maybe have a way to temporarily hold on to a "slot" so that we can ensure
                         // we don't go over this limit if multiple connections come in simultaneously.
                         if active_peers.len() >= limit {
                             // Connection doesn't meet the requirements to bypass the limit so bail
+                            connection.close_with_reason(
+                                close_code::CONNECTION_LIMIT,
+                                b"connection limit reached",
+                            );
                             return Err(anyhow::anyhow!(
                                 "dropping connection from peer {} due to connection limits",
                                 connection.peer_id()
@@ -276,7 +575,7 @@ impl ConnectionManager {
                 }
             }
 
-            super::wire::handshake(connection).await
+            handshake.accept().await
         };
 
         let connecting_result = tokio::time::timeout(config.connect_timeout(), fut)
@@ -289,6 +588,8 @@ impl ConnectionManager {
             maybe_oneshot: None,
             target_address: None,
             target_peer_id: None,
+            accepted_from: Some(remote_address),
+            cancelled: false,
         }
     }
 
@@ -299,16 +600,36 @@ impl ConnectionManager {
             maybe_oneshot,
             target_address,
             target_peer_id,
+            accepted_from,
+            cancelled,
         }: ConnectingOutput,
     ) {
-        match connecting_result {
+        // Connections made to an address, rather than to a specific peer, can only be checked
+        // against the set of banned peers once the connection has been established
+        let connecting_result = connecting_result.and_then(|connection| {
+            if self.banned_peers.is_banned(&connection.peer_id()) {
+                connection.close_with_reason(close_code::BANNED, b"peer is banned");
+                Err(anyhow::Error::new(ConnectError::Banned).context(format!(
+                    "refusing connection to banned peer {}",
+                    connection.peer_id()
+                )))
+            } else {
+                Ok(connection)
+            }
+        });
+
+        let outcome = match connecting_result {
             Ok(new_connection) => {
                 let peer_id = new_connection.peer_id();
                 debug!(peer_id =% peer_id, "new connection");
+                if accepted_from.is_some() {
+                    self.endpoint_counters.connection_accepted();
+                }
                 self.add_peer(new_connection);
                 if let Some(oneshot) = maybe_oneshot {
                     let _ = oneshot.send(Ok(peer_id));
                 }
+                Ok(peer_id)
             }
             Err(e) => {
                 debug!(
@@ -316,16 +637,94 @@ impl ConnectionManager {
                     target_peer_id = ?target_peer_id,
                     "connecting failed: {e}"
                 );
+                if let Some(address) = accepted_from {
+                    self.endpoint_counters.connection_rejected();
+                    self.active_peers.report_accept_error(AcceptError {
+                        address,
+                        error: e.to_string(),
+                    });
+                }
+                let connect_error = e
+                    .downcast_ref::<ConnectError>()
+                    .cloned()
+                    .unwrap_or_else(|| ConnectError::Transport(e.to_string()));
                 if let Some(oneshot) = maybe_oneshot {
                     let _ = oneshot.send(Err(e));
                 }
+                Err(connect_error)
+            }
+        };
+
+        if let Some(address) = target_address {
+            if let Some(waiters) = self
+                .coalesced_dials
+                .remove(&(address.clone(), target_peer_id))
+            {
+                self.complete_coalesced_dials(address, target_peer_id, waiters, cancelled, outcome);
             }
         }
+
+        // If this was a background dial, process its result right away so that its backoff state
+        // is updated promptly
+        if target_peer_id.is_some_and(|peer_id| self.pending_dials.contains_key(&peer_id)) {
+            self.handle_connectivity_check(std::time::Instant::now());
+        }
+    }
+
+    /// Hand the outcome of a dial to the callers which joined it while it was in progress.
+    fn complete_coalesced_dials(
+        &mut self,
+        address: Address,
+        target_peer_id: Option<PeerId>,
+        waiters: Vec<DialWaiter>,
+        cancelled: bool,
+        outcome: std::result::Result<PeerId, ConnectError>,
+    ) {
+        let waiters = waiters.into_iter().filter(|waiter| !waiter.is_closed());
+
+        match outcome {
+            Ok(peer_id) => {
+                for waiter in waiters {
+                    let _ = waiter.send(Ok(peer_id));
+                }
+            }
+            // The dial was abandoned because the caller which started it stopped waiting on it, so
+            // start it again on behalf of everyone who is still waiting. Any other failure is
+            // handed to them as is, even if the caller which started the dial has since gone away
+            Err(_) if cancelled => {
+                for waiter in waiters {
+                    self.dial_peer(address.clone(), target_peer_id, waiter);
+                }
+            }
+            Err(e) => {
+                for waiter in waiters {
+                    let _ = waiter.send(Err(e.clone().into()));
+                }
+            }
+        }
+    }
+
+    /// Returns the earliest point in the future at which a peer's dial backoff will elapse, if any.
+    fn next_dial_backoff_deadline(&self) -> Option<std::time::Instant> {
+        let now = std::time::Instant::now();
+        self.dial_backoff_states
+            .iter()
+            .filter(|(peer_id, _)| !self.pending_dials.contains_key(peer_id))
+            .map(|(_, state)| state.backoff)
+            .filter(|backoff| *backoff > now)
+            .min()
     }
 
     // TODO maybe look into marking an address as invalid if we weren't able to connect due to a
     // mismatching cryptographic identity
     fn handle_connectivity_check(&mut self, now: std::time::Instant) {
+        // Only remember the peers we may need to reconnect to
+        {
+            let known_peers = self.known_peers.inner();
+            self.previously_connected
+                .retain(|peer_id| known_peers.contains_key(peer_id));
+        }
+
         // Drain any completed dials by checking if the oneshot channel has been filled or not
         self.pending_dials
             .retain(|peer_id, oneshot| match oneshot.try_recv() {
@@ -339,20 +738,17 @@ impl ConnectionManager {
 
                 // Dialing failed for some reason
                 Ok(Err(_)) => {
+                    let backoff = if self.previously_connected.contains(peer_id) {
+                        Backoff::exponential(&self.config.reconnect())
+                    } else {
+                        Backoff::linear(&self.config)
+                    };
                     match self.dial_backoff_states.entry(*peer_id) {
                         Entry::Occupied(mut entry) => {
-                            entry.get_mut().update(
-                                now,
-                                self.config.connection_backoff(),
-                                self.config.max_connection_backoff(),
-                            );
+                            entry.get_mut().update(now, backoff);
                         }
                         Entry::Vacant(entry) => {
-                            entry.insert(DialBackoffState::new(
-                                now,
-                                self.config.connection_backoff(),
-                                self.config.max_connection_backoff(),
-                            ));
+                            entry.insert(DialBackoffState::new(now, backoff));
                         }
                     }
 
@@ -374,10 +770,11 @@ impl ConnectionManager {
             known_peers
                 .values()
                 .filter(|peer_info| {
-                    !matches!(peer_info.affinity, PeerAffinity::Never)
+                    matches!(peer_info.affinity, PeerAffinity::High) // We only maintain connections with high affinity peers
                     && peer_info.peer_id != self.endpoint.peer_id() // We don't dial ourself
-                    && !peer_info.address.is_empty() // The peer has an address we can dial
+                    && (!peer_info.address.is_empty() || self.peer_resolver.is_some()) // The peer has an address we can dial, or one can be resolved
                     && !active_peers.contains(&peer_info.peer_id) // The node is not already connected.
+                    && !self.banned_peers.is_banned(&peer_info.peer_id) // The node is not banned.
                     && !self.pending_dials.contains_key(&peer_info.peer_id) // There is no pending dial to this node.
                     && self.dial_backoff_states  // check that `now` is after the backoff time, if it exists
                         .get(&peer_info.peer_id)
@@ -398,22 +795,107 @@ impl ConnectionManager {
 
         for mut peer in eligible.into_iter().take(number_to_dial) {
             let (sender, receiver) = oneshot::channel();
-
-            // Select the index of the address to dial by mapping the number of attempts we've made
-            // so far into the Peer's known addresses
-            let idx = self
+            let attempts = self
                 .dial_backoff_states
                 .get(&peer.peer_id)
                 .map(|state| state.attempts)
-                .unwrap_or(0)
-                % peer.address.len();
-
-            let address = peer.address.remove(idx);
-            self.dial_peer(address, Some(peer.peer_id), sender);
+                .unwrap_or(0);
+
+            match (peer.address.is_empty(), &self.peer_resolver) {
+                (true, Some(resolver)) => {
+                    let resolver = resolver.clone();
+                    let peer_id = peer.peer_id;
+                    let timeout = self.config.connect_timeout();
+                    self.pending_resolutions.spawn(async move {
+                        // Bound resolution by the connect timeout so that a stalled resolver
+                        // can't keep the peer's dial pending forever
+                        let addresses = tokio::time::timeout(timeout, resolver.resolve(peer_id))
+                            .await
+                            .map_err(Into::into)
+                            .and_then(std::convert::identity);
+                        ResolvedPeer {
+                            peer_id,
+                            addresses,
+                            attempts,
+                            oneshot: sender,
+                        }
+                    });
+                }
+                _ => {
+                    // Select the index of the address to dial by mapping the number of attempts
+                    // we've made so far into the Peer's known addresses
+                    let address = peer.address.remove(attempts % peer.address.len());
+                    self.queue_dial(address, Some(peer.peer_id), sender);
+                }
+            }
             self.pending_dials.insert(peer.peer_id, receiver);
         }
     }
 
+    fn handle_resolved_peer(&mut self, resolved_peer: ResolvedPeer) {
+        let ResolvedPeer {
+            peer_id,
+            addresses,
+            attempts,
+            oneshot,
+        } = resolved_peer;
+
+        let error = match addresses {
+            Ok(addresses) if !addresses.is_empty() => {
+                let address = addresses[attempts % addresses.len()];
+                self.queue_dial(address.into(), Some(peer_id), oneshot);
+                return;
+            }
+            Ok(_) => anyhow::anyhow!("no addresses were resolved for peer {peer_id}"),
+            Err(e) => e.context(format!("unable to resolve addresses for peer {peer_id}")),
+        };
+        debug!("{error:#}");
+        let _ = oneshot.send(Err(error));
+
+        // Process the failure right away so that the peer is backed off from
+        self.handle_connectivity_check(std::time::Instant::now());
+    }
+
+    /// Dial a peer, subject to the configured outbound dial rate limit.
+    fn queue_dial(
+        &mut self,
+        address: Address,
+        peer_id: Option<PeerId>,
+        oneshot: oneshot::Sender<Result<PeerId>>,
+    ) {
+        self.queued_dials.push_back(QueuedDial {
+            address,
+            peer_id,
+            oneshot,
+        });
+        self.process_queued_dials(std::time::Instant::now());
+    }
+
+    /// Start as many queued dials as the outbound dial rate limit allows.
+    fn process_queued_dials(&mut self, now: std::time::Instant) {
+        let dial_interval = self.config.outbound_dial_interval();
+
+        while !self.queued_dials.is_empty() && (dial_interval.is_none() || now >= self.next_dial_at)
+        {
+            let QueuedDial {
+                address,
+                peer_id,
+                oneshot,
+            } = self.queued_dials.pop_front().unwrap();
+
+            // Don't spend any of the budget on dials which nobody is waiting on anymore
+            if oneshot.is_closed() {
+                continue;
+            }
+
+            self.dial_peer(address, peer_id, oneshot);
+
+            if let Some(dial_interval) = dial_interval {
+                self.next_dial_at = std::cmp::max(self.next_dial_at, now) + dial_interval;
+            }
+        }
+    }
+
     #[instrument(level = "trace", skip_all, fields(peer_id = ?peer_id, address = ?address))]
     fn dial_peer(
         &mut self,
@@ -421,47 +903,232 @@ impl ConnectionManager {
         peer_id: Option<PeerId>,
         oneshot: oneshot::Sender<Result<PeerId>>,
     ) {
-        let target_address = address.clone();
-        let maybe_connecting = if let Some(peer_id) = peer_id {
-            self.endpoint
-                .connect_with_expected_peer_id(address, peer_id)
-        } else {
-            self.endpoint.connect(address)
-        };
+        if let Some(peer_id) = peer_id.filter(|peer_id| self.banned_peers.is_banned(peer_id)) {
+            let _ = oneshot.send(Err(anyhow::Error::new(ConnectError::Banned)
+                .context(format!("refusing to connect to banned peer {peer_id}"))));
+            return;
+        }
+
+        if peer_id == Some(self.endpoint.peer_id()) {
+            let _ = oneshot.send(Err(ConnectError::attach(SelfConnection(()).into())));
+            return;
+        }
+
+        // Join an identical dial if one is already in progress rather than running another
+        // handshake, which would only result in a duplicate connection
+        let key = (address.clone(), peer_id);
+        if let Some(waiters) = self.coalesced_dials.get_mut(&key) {
+            trace!("joining in-progress dial");
+            waiters.push(oneshot);
+            return;
+        }
+        self.coalesced_dials.insert(key, Vec::new());
+
         self.pending_connections.spawn(Self::dial_peer_task(
-            maybe_connecting,
-            target_address,
+            self.endpoints().cloned().collect(),
+            address,
             peer_id,
             oneshot,
+            self.endpoint.peer_id(),
             self.config.clone(),
+            self.connect_hook.clone(),
+            self.capabilities.clone(),
         ));
     }
 
     // TODO maybe look at cloning the endpoint so that we can try multiple addresses in the event
     // Address resolves to multiple ips.
+    #[instrument(
+        name = "connect",
+        level = "debug",
+        skip_all,
+        fields(remote_addr = %target_address, peer_id = tracing::field::Empty)
+    )]
+    #[allow(clippy::too_many_arguments)]
     async fn dial_peer_task(
-        maybe_connecting: Result<Connecting>,
+        endpoints: Vec<Arc<Endpoint>>,
         target_address: Address,
         peer_id: Option<PeerId>,
-        oneshot: oneshot::Sender<Result<PeerId>>,
+        mut oneshot: oneshot::Sender<Result<PeerId>>,
+        own_peer_id: PeerId,
         config: Arc<Config>,
+        connect_hook: Option<ConnectHook>,
+        capabilities: Arc<[String]>,
     ) -> ConnectingOutput {
         let fut = async {
-            let connection = maybe_connecting?.await?;
+            // Resolve the address as part of the dial, rather than on the event loop, so that a
+            // slow DNS lookup doesn't stall the connection manager and is bound by the timeout
+            let addr = target_address.lookup().await?;
+            let endpoint = endpoint_for(&endpoints, addr);
+            let connecting = if let Some(peer_id) = peer_id {
+                endpoint.connect_with_expected_peer_id(addr.into(), peer_id)
+            } else {
+                endpoint.connect(addr.into())
+            };
+            let connection = connecting?.await?;
+            tracing::Span::current()
+                .record("peer_id", tracing::field::display(connection.peer_id()));
+
+            // Dialing an address, e.g. one learned from another peer, can lead back to ourself
+            reject_self_connection(own_peer_id, &connection)?;
+
+            // The expected identity is already enforced during the TLS handshake, but double check
+            // it here to ensure a mismatched connection is never handed out
+            if let Some(peer_id) = peer_id {
+                if connection.peer_id() != peer_id {
+                    connection.close();
+                    return Err(anyhow::Error::new(ConnectError::PeerIdMismatch {
+                        expected: peer_id,
+                    })
+                    .context(format!(
+                        "expected to connect to peer {peer_id} but connected to {}",
+                        connection.peer_id()
+                    )));
+                }
+            }
+
+            run_connect_hook(connect_hook.as_ref(), &connection).await?;
 
-            super::wire::handshake(connection).await
+            super::wire::handshake(connection, &config, &capabilities).await
         };
 
-        let connecting_result = tokio::time::timeout(config.connect_timeout(), fut)
-            .await
-            .map_err(Into::into)
-            .and_then(std::convert::identity);
+        let mut cancelled = false;
+        let connecting_result = tokio::select! {
+            result = tokio::time::timeout(config.connect_timeout(), fut) => {
+                result.map_err(Into::into).and_then(std::convert::identity)
+            }
+            // Abandon the dial, dropping the in-progress connection attempt, if the requester is
+            // no longer waiting on the result (e.g. the request was cancelled or timed out)
+            _ = oneshot.closed() => {
+                cancelled = true;
+                Err(anyhow::anyhow!("connection attempt to {target_address} was cancelled"))
+            }
+        }
+        .map_err(ConnectError::attach);
 
         ConnectingOutput {
             connecting_result,
             maybe_oneshot: Some(oneshot),
             target_address: Some(target_address),
             target_peer_id: peer_id,
+            accepted_from: None,
+            cancelled,
+        }
+    }
+}
+
+/// Select the endpoint to dial `addr` from, preferring the first endpoint bound to the same
+/// address family as `addr`. `endpoints` always starts with the primary endpoint.
+fn endpoint_for(endpoints: &[Arc<Endpoint>], addr: SocketAddr) -> &Arc<Endpoint> {
+    endpoints
+        .iter()
+        .find(|endpoint| endpoint.local_addr().is_ipv4() == addr.is_ipv4())
+        .unwrap_or(&endpoints[0])
+}
+
+/// Close `connection`, returning a [`SelfConnection`] error, if it was made with ourself.
+fn reject_self_connection(own_peer_id: PeerId, connection: &Connection) -> Result<()> {
+    if connection.peer_id() == own_peer_id {
+        connection.close_with_reason(close_code::SELF_CONNECTION, b"connected to self");
+        return Err(SelfConnection(()).into());
+    }
+
+    Ok(())
+}
+
+/// Run the connect hook, if one is configured, closing the connection if the hook rejects it.
+async fn run_connect_hook(
+    connect_hook: Option<&ConnectHook>,
+    connection: &Connection,
+) -> Result<()> {
+    let connect_hook = match connect_hook {
+        Some(connect_hook) => connect_hook,
+        None => return Ok(()),
+    };
+
+    if let Err(e) = connect_hook(connection.peer_id(), connection.remote_address()).await {
+        connection.close_with_reason(close_code::REJECTED, e.to_string().as_bytes());
+        return Err(ConnectError::Refused(format!(
+            "connection with peer {} rejected: {e}",
+            connection.peer_id()
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Accept the next incoming connection on any of the provided endpoints, or wait forever if there
+/// are none.
+async fn accept_any(endpoints: &[Arc<Endpoint>]) -> Option<Connecting> {
+    if endpoints.is_empty() {
+        return futures::future::pending().await;
+    }
+
+    let (connecting, _, _) =
+        futures::future::select_all(endpoints.iter().map(|endpoint| Box::pin(endpoint.accept())))
+            .await;
+    connecting
+}
+
+/// Sleep until the provided deadline, or forever if there is none.
+async fn sleep_until(deadline: Option<std::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// How the delay between consecutive attempts to dial a peer grows.
+#[derive(Clone, Copy, Debug)]
+enum Backoff {
+    /// The delay grows by `step` after each failed attempt, up to `max`.
+    Linear {
+        step: std::time::Duration,
+        max: std::time::Duration,
+    },
+    /// The delay starts at `base` and doubles after each failed attempt, up to `max`, after which
+    /// up to `jitter` of it is randomly added.
+    Exponential {
+        base: std::time::Duration,
+        max: std::time::Duration,
+        jitter: f64,
+    },
+}
+
+impl Backoff {
+    fn linear(config: &Config) -> Self {
+        Self::Linear {
+            step: config.connection_backoff(),
+            max: config.max_connection_backoff(),
+        }
+    }
+
+    fn exponential(config: &ReconnectConfig) -> Self {
+        Self::Exponential {
+            base: config.base_delay(),
+            max: config.max_delay(),
+            jitter: config.jitter(),
+        }
+    }
+
+    /// The delay to wait after the provided number of failed attempts.
+    fn delay(&self, attempts: usize) -> std::time::Duration {
+        match *self {
+            Self::Linear { step, max } => std::cmp::min(
+                max,
+                step.saturating_mul(attempts.try_into().unwrap_or(u32::MAX)),
+            ),
+            Self::Exponential { base, max, jitter } => {
+                let exponent = u32::try_from(attempts - 1).unwrap_or(u32::MAX);
+                let delay = std::cmp::min(
+                    max,
+                    base.saturating_mul(2_u32.checked_pow(exponent).unwrap_or(u32::MAX)),
+                );
+                // Jitter is added after capping the delay so that peers which have been failing
+                // for a while don't all retry in lockstep at `max`
+                delay + delay.mul_f64(jitter * rand::random::<f64>())
+            }
         }
     }
 }
@@ -477,34 +1144,19 @@ struct DialBackoffState {
 }
 
 impl DialBackoffState {
-    fn new(
-        now: std::time::Instant,
-        backoff_step: std::time::Duration,
-        max_backoff: std::time::Duration,
-    ) -> Self {
+    fn new(now: std::time::Instant, backoff: Backoff) -> Self {
         let mut state = Self {
             backoff: now,
             attempts: 0,
         };
 
-        state.update(now, backoff_step, max_backoff);
+        state.update(now, backoff);
         state
     }
 
-    fn update(
-        &mut self,
-        now: std::time::Instant,
-        backoff_step: std::time::Duration,
-        max_backoff: std::time::Duration,
-    ) {
+    fn update(&mut self, now: std::time::Instant, backoff: Backoff) {
         self.attempts += 1;
-
-        let backoff_duration = std::cmp::min(
-            max_backoff,
-            backoff_step.saturating_mul(self.attempts.try_into().unwrap_or(u32::MAX)),
-        );
-
-        self.backoff = now + backoff_duration;
+        self.backoff = now + backoff.delay(self.attempts);
     }
 }
 
@@ -514,15 +1166,57 @@ impl DialBackoffState {
 pub(crate) struct ActivePeers(Arc<RwLock<ActivePeersInner>>);
 
 impl ActivePeers {
-    pub fn new(channel_size: usize) -> Self {
-        Self(Arc::new(RwLock::new(ActivePeersInner::new(channel_size))))
+    pub fn new(channel_size: usize, max_peers: Option<usize>, metrics: Metrics) -> Self {
+        Self(Arc::new(RwLock::new(ActivePeersInner::new(
+            channel_size,
+            max_peers,
+            metrics,
+        ))))
+    }
+
+    /// The maximum number of concurrent connections, if any, see
+    /// [`Config::max_concurrent_connections`](crate::Config::max_concurrent_connections).
+    pub fn max_peers(&self) -> Option<usize> {
+        self.inner().max_peers
+    }
+
+    /// Change the maximum number of concurrent connections to `max_peers`, evicting peers until
+    /// the number of connections is within the new limit.
+    ///
+    /// Peers are evicted in the same order as when making room for a peer with a higher affinity,
+    /// so peers with [`PeerAffinity::High`] are never evicted and may keep the number of
+    /// connections above the limit.
+    pub fn set_max_peers(&self, max_peers: usize, known_peers: &KnownPeers) {
+        let known_peers = known_peers.inner();
+        let mut inner = self.inner_mut();
+        inner.max_peers = Some(max_peers);
+        while inner.connections.len() > max_peers {
+            if inner.evict_one(&known_peers).is_none() {
+                break;
+            }
+        }
     }
 
-    #[allow(unused)]
     pub fn subscribe(&self) -> (broadcast::Receiver<PeerEvent>, Vec<PeerId>) {
         self.inner().subscribe()
     }
 
+    pub fn subscribe_filtered(
+        &self,
+        filter: PeerEventFilter,
+    ) -> (broadcast::Receiver<PeerEvent>, Vec<PeerId>) {
+        self.inner_mut().subscribe_filtered(filter)
+    }
+
+    pub fn subscribe_accept_errors(&self) -> broadcast::Receiver<AcceptError> {
+        self.inner().accept_error_sender.subscribe()
+    }
+
+    pub fn report_accept_error(&self, error: AcceptError) {
+        // We don't care if anyone is listening
+        let _ = self.inner().accept_error_sender.send(error);
+    }
+
     pub fn peers(&self) -> Vec<PeerId> {
         self.inner().peers()
     }
@@ -531,18 +1225,52 @@ impl ActivePeers {
         self.inner().get(peer_id)
     }
 
+    /// Returns a snapshot of all active connections, taken while holding the lock so that it
+    /// reflects a single point in time.
+    pub fn connections(&self) -> Vec<Connection> {
+        self.inner().connections.values().cloned().collect()
+    }
+
     pub fn remove(&self, peer_id: &PeerId, reason: DisconnectReason) {
         self.inner_mut().remove(peer_id, reason)
     }
 
+    /// Remove `peer_id`, closing its connection with the provided application error `code` and
+    /// `close_reason`.
+    pub fn remove_with_close_code(
+        &self,
+        peer_id: &PeerId,
+        reason: DisconnectReason,
+        code: quinn::VarInt,
+        close_reason: &[u8],
+    ) {
+        self.inner_mut()
+            .remove_with_close_code(peer_id, reason, code, close_reason)
+    }
+
+    /// Remove all peers, closing their connections with the provided application error `code` and
+    /// `close_reason`.
+    pub fn remove_all_with_close_code(
+        &self,
+        reason: DisconnectReason,
+        code: quinn::VarInt,
+        close_reason: &[u8],
+    ) {
+        let mut inner = self.inner_mut();
+        for peer_id in inner.peers() {
+            inner.remove_with_close_code(&peer_id, reason.clone(), code, close_reason);
+        }
+    }
+
     pub fn remove_with_stable_id(
         &self,
         peer_id: PeerId,
         stable_id: usize,
         reason: DisconnectReason,
+        cause: DisconnectCause,
     ) {
         self.inner_mut()
-            .remove_with_stable_id(peer_id, stable_id, reason)
+            .remove_with_stable_id(peer_id, stable_id, reason, cause)
     }
 
     #[must_use]
@@ -550,6 +1278,13 @@ impl ActivePeers {
         self.inner_mut().add(own_peer_id, new_connection)
     }
 
+    /// Evict a single peer which isn't a known peer with [`PeerAffinity::High`], returning the
+    /// [`PeerId`] of the evicted peer if there was one.
+    fn evict_one(&self, known_peers: &KnownPeers) -> Option<PeerId> {
+        let known_peers = known_peers.inner();
+        self.inner_mut().evict_one(&known_peers)
+    }
+
     fn inner(&self) -> std::sync::RwLockReadGuard<'_, ActivePeersInner> {
         self.0.read().unwrap()
     }
@@ -558,10 +1293,31 @@ impl ActivePeers {
         self.0.write().unwrap()
     }
 
-    fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         self.inner().len()
     }
 
+    /// Returns the number of inbound connections established from `ip`, not counting those from
+    /// known peers with [`PeerAffinity::High`] as they are exempt from the per-ip limit.
+    pub fn inbound_connections_from(&self, ip: IpAddr, known_peers: &KnownPeers) -> usize {
+        let known_peers = known_peers.inner();
+        self.inner()
+            .connections
+            .values()
+            .filter(|connection| {
+                connection.origin() == ConnectionOrigin::Inbound
+                    && connection.remote_address().ip() == ip
+                    && !matches!(
+                        known_peers.get(&connection.peer_id()),
+                        Some(PeerInfo {
+                            affinity: PeerAffinity::High,
+                            ..
+                        })
+                    )
+            })
+            .count()
+    }
+
     pub fn downgrade(&self) -> ActivePeersRef {
         ActivePeersRef(Arc::downgrade(&self.0))
     }
@@ -576,22 +1332,70 @@ impl ActivePeersRef {
     }
 }
 
+/// A subscriber which is only sent the [`PeerEvent`]s matching its filter.
+struct FilteredSubscriber {
+    filter: PeerEventFilter,
+    sender: broadcast::Sender<PeerEvent>,
+}
+
+impl std::fmt::Debug for FilteredSubscriber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FilteredSubscriber")
+            .field("sender", &self.sender)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 struct ActivePeersInner {
     connections: HashMap<PeerId, Connection>,
+    channel_size: usize,
     peer_event_sender: broadcast::Sender<PeerEvent>,
+    filtered_subscribers: Vec<FilteredSubscriber>,
+    accept_error_sender: broadcast::Sender<AcceptError>,
+    max_peers: Option<usize>,
+    metrics: Metrics,
 }
 
 impl ActivePeersInner {
-    fn new(channel_size: usize) -> Self {
+    fn new(channel_size: usize, max_peers: Option<usize>, metrics: Metrics) -> Self {
         let (sender, _receiver) = broadcast::channel(channel_size);
+        let (accept_error_sender, _receiver) = broadcast::channel(channel_size);
         Self {
             connections: Default::default(),
+            channel_size,
             peer_event_sender: sender,
+            filtered_subscribers: Vec::new(),
+            accept_error_sender,
+            max_peers,
+            metrics,
         }
     }
 
+    /// Subscribe to the events matching `filter`, returning a receiver for them along with a
+    /// snapshot of the currently connected peers whose [`PeerEvent::NewPeer`] event would have
+    /// matched.
+    fn subscribe_filtered(
+        &mut self,
+        filter: PeerEventFilter,
+    ) -> (broadcast::Receiver<PeerEvent>, Vec<PeerId>) {
+        let peers = self
+            .connections
+            .iter()
+            .filter(|(peer_id, connection)| {
+                filter(&PeerEvent::NewPeer(**peer_id, connection.origin()))
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        let (sender, receiver) = broadcast::channel(self.channel_size);
+        self.filtered_subscribers
+            .push(FilteredSubscriber { filter, sender });
+        (receiver, peers)
+    }
+
     fn subscribe(&self) -> (broadcast::Receiver<PeerEvent>, Vec<PeerId>) {
+        // Events are only ever sent while holding the write lock, so taking the snapshot and
+        // subscribing while holding the read lock ensures no events are missed in between.
         let peers = self.peers();
         let receiver = self.peer_event_sender.subscribe();
         (receiver, peers)
@@ -614,11 +1418,31 @@ impl ActivePeersInner {
     }
 
     fn remove(&mut self, peer_id: &PeerId, reason: DisconnectReason) {
-        if let Some(connection) = self.connections.remove(peer_id) {
-            // maybe actually provide reason to other side?
-            connection.close();
+        self.remove_with_close_code(
+            peer_id,
+            reason,
+            close_code::CLOSED.into(),
+            b"connection closed",
+        )
+    }
 
-            self.send_event(PeerEvent::LostPeer(*peer_id, reason));
+    fn remove_with_close_code(
+        &mut self,
+        peer_id: &PeerId,
+        reason: DisconnectReason,
+        code: quinn::VarInt,
+        close_reason: &[u8],
+    ) {
+        if let Some(connection) = self.connections.remove(peer_id) {
+            connection.close_with_reason(code, close_reason);
+
+            self.metrics
+                .connection_lost(*peer_id, &reason, self.connections.len());
+            self.send_event(PeerEvent::LostPeer(
+                *peer_id,
+                reason,
+                DisconnectCause::default(),
+            ));
         }
     }
 
@@ -627,6 +1451,7 @@ impl ActivePeersInner {
         peer_id: PeerId,
         stable_id: usize,
         reason: DisconnectReason,
+        cause: DisconnectCause,
     ) {
         match self.connections.entry(peer_id) {
             Entry::Occupied(entry) => {
@@ -636,21 +1461,69 @@ impl ActivePeersInner {
                     // maybe actually provide reason to other side?
                     connection.close();
 
-                    self.send_event(PeerEvent::LostPeer(peer_id, reason));
+                    self.metrics
+                        .connection_lost(peer_id, &reason, self.connections.len());
+                    self.send_event(PeerEvent::LostPeer(peer_id, reason, cause));
                 }
             }
             Entry::Vacant(_) => {}
         }
     }
 
-    fn send_event(&self, event: PeerEvent) {
+    /// Select a peer to evict, preferring peers which aren't known or have
+    /// [`PeerAffinity::Never`], followed by those with [`PeerAffinity::Allowed`]. Within each group
+    /// the least recently used peer is picked. Peers with [`PeerAffinity::High`] are never evicted.
+    fn evict_one(&mut self, known_peers: &HashMap<PeerId, PeerInfo>) -> Option<PeerId> {
+        let peer_id = self
+            .connections
+            .values()
+            .filter_map(|connection| {
+                let priority = match known_peers.get(&connection.peer_id()) {
+                    Some(PeerInfo {
+                        affinity: PeerAffinity::High,
+                        ..
+                    }) => return None,
+                    None
+                    | Some(PeerInfo {
+                        affinity: PeerAffinity::Never,
+                        ..
+                    }) => 0,
+                    Some(PeerInfo {
+                        affinity: PeerAffinity::Allowed,
+                        ..
+                    }) => 1,
+                };
+                Some((priority, connection.last_activity(), connection.peer_id()))
+            })
+            .min()
+            .map(|(_, _, peer_id)| peer_id)?;
+
+        debug!("evicting peer {peer_id} to make room for a peer with a higher affinity");
+        self.remove(&peer_id, DisconnectReason::Evicted);
+        Some(peer_id)
+    }
+
+    fn send_event(&mut self, event: PeerEvent) {
+        // Filters are applied before delivery so that filtered subscribers are only woken for
+        // matching events, and subscribers which have gone away are dropped along the way
+        self.filtered_subscribers.retain(|subscriber| {
+            if subscriber.sender.receiver_count() == 0 {
+                return false;
+            }
+            if (subscriber.filter)(&event) {
+                let _ = subscriber.sender.send(event.clone());
+            }
+            true
+        });
+
         // We don't care if anyone is listening
         let _ = self.peer_event_sender.send(event);
     }
 
     #[must_use]
     fn add(&mut self, own_peer_id: &PeerId, new_connection: Connection) -> Option<Connection> {
-        // TODO drop Connection if you've somehow connected out ourself
+        // Connections with ourself are rejected before they make it here
+        debug_assert_ne!(own_peer_id, &new_connection.peer_id());
 
         let peer_id = new_connection.peer_id();
         match self.connections.entry(peer_id) {
@@ -663,11 +1536,26 @@ impl ActivePeersInner {
                 ) {
                     debug!("closing old connection with {peer_id:?} to mitigate simultaneous dial");
                     let old_connection = entry.insert(new_connection.clone());
-                    old_connection.close();
-                    self.send_event(PeerEvent::LostPeer(peer_id, DisconnectReason::Requested));
+                    old_connection.close_with_reason(
+                        close_code::DUPLICATE_CONNECTION,
+                        b"duplicate connection",
+                    );
+                    self.metrics.connection_lost(
+                        peer_id,
+                        &DisconnectReason::DuplicateConnection,
+                        self.connections.len(),
+                    );
+                    self.send_event(PeerEvent::LostPeer(
+                        peer_id,
+                        DisconnectReason::DuplicateConnection,
+                        DisconnectCause::default(),
+                    ));
                 } else {
                     debug!("closing new connection with {peer_id:?} to mitigate simultaneous dial");
-                    new_connection.close();
+                    new_connection.close_with_reason(
+                        close_code::DUPLICATE_CONNECTION,
+                        b"duplicate connection",
+                    );
                     // Early return to avoid standing up Incoming Request handlers
                     return None;
                 }
@@ -677,7 +1565,12 @@ impl ActivePeersInner {
             }
         }
 
-        self.send_event(PeerEvent::NewPeer(peer_id));
+        self.metrics.connection_established(
+            peer_id,
+            new_connection.origin(),
+            self.connections.len(),
+        );
+        self.send_event(PeerEvent::NewPeer(peer_id, new_connection.origin()));
 
         Some(new_connection)
     }
@@ -707,6 +1600,105 @@ impl ActivePeersInner {
     }
 }
 
+/// Counters for activity across all connections of a [`Network`](super::Network), reported via
+/// [`Network::endpoint_stats`](super::Network::endpoint_stats).
+#[derive(Debug, Default)]
+pub(crate) struct EndpointCounters {
+    accepted_connections: AtomicU64,
+    rejected_connections: AtomicU64,
+    datagrams_sent: AtomicU64,
+    datagrams_received: AtomicU64,
+    datagrams_dropped: AtomicU64,
+}
+
+impl EndpointCounters {
+    pub fn connection_accepted(&self) {
+        self.accepted_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn datagram_sent(&self) {
+        self.datagrams_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn datagram_received(&self) {
+        self.datagrams_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn datagrams_dropped(&self, count: usize) {
+        self.datagrams_dropped
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, active_connections: usize) -> EndpointStats {
+        EndpointStats {
+            accepted_connections: self.accepted_connections.load(Ordering::Relaxed),
+            rejected_connections: self.rejected_connections.load(Ordering::Relaxed),
+            active_connections: active_connections as u64,
+            datagrams_sent: self.datagrams_sent.load(Ordering::Relaxed),
+            datagrams_received: self.datagrams_received.load(Ordering::Relaxed),
+            datagrams_dropped: self.datagrams_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A handle that keeps track of all the peers which are banned, along with when their ban expires.
+///
+/// Banned peers are disconnected, their inbound connections are rejected and no outbound
+/// connections will be made to them until they are unbanned or their ban expires.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BannedPeers(Arc<RwLock<HashMap<PeerId, Option<std::time::Instant>>>>);
+
+impl BannedPeers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Ban a peer for the provided duration, or indefinitely if no duration is provided.
+    ///
+    /// Any other bans which have expired are pruned at the same time, so that peers which are
+    /// never looked up again don't accumulate.
+    pub fn ban(&self, peer_id: PeerId, duration: Option<std::time::Duration>) {
+        let now = std::time::Instant::now();
+        let expiry = duration.map(|duration| now + duration);
+        let mut banned_peers = self.0.write().unwrap();
+        banned_peers.retain(|_, expiry| !is_expired(*expiry, now));
+        banned_peers.insert(peer_id, expiry);
+    }
+
+    pub fn unban(&self, peer_id: &PeerId) {
+        self.0.write().unwrap().remove(peer_id);
+    }
+
+    /// Returns whether `peer_id` is currently banned, removing its ban if it has expired.
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        let now = std::time::Instant::now();
+        match self.0.read().unwrap().get(peer_id) {
+            Some(expiry) if !is_expired(*expiry, now) => return true,
+            Some(_) => {}
+            None => return false,
+        }
+
+        // The ban has expired. Re-check under the write lock in case the peer was banned again
+        // in the meantime.
+        let mut banned_peers = self.0.write().unwrap();
+        if banned_peers
+            .get(peer_id)
+            .is_some_and(|expiry| is_expired(*expiry, now))
+        {
+            banned_peers.remove(peer_id);
+        }
+        false
+    }
+}
+
+fn is_expired(expiry: Option<std::time::Instant>, now: std::time::Instant) -> bool {
+    expiry.is_some_and(|expiry| expiry <= now)
+}
+
 /// A handle that keeps all the peers which we have registered and are known to us.
 #[derive(Clone, Debug, Default)]
 pub struct KnownPeers(Arc<RwLock<HashMap<PeerId, PeerInfo>>>);
@@ -732,10 +1724,57 @@ impl KnownPeers {
         self.inner().values().cloned().collect()
     }
 
+    /// Returns a snapshot of all known peers.
+    ///
+    /// Along with [`KnownPeers::load`] this can be used to persist the set of known peers across
+    /// restarts.
+    pub fn snapshot(&self) -> Vec<PeerInfo> {
+        self.get_all()
+    }
+
+    /// Returns a snapshot of all known peers with the provided `affinity`.
+    pub fn peers_with_affinity(&self, affinity: PeerAffinity) -> Vec<PeerInfo> {
+        self.inner()
+            .values()
+            .filter(|peer_info| peer_info.affinity == affinity)
+            .cloned()
+            .collect()
+    }
+
     pub fn insert(&self, peer_info: PeerInfo) -> Option<PeerInfo> {
         self.inner_mut().insert(peer_info.peer_id, peer_info)
     }
 
+    /// Change the affinity of a known peer in place.
+    ///
+    /// The new affinity is used by all subsequent connectivity checks and eviction decisions.
+    /// Upgrading a peer to [`PeerAffinity::High`] causes it to be dialed in the background if
+    /// we aren't already connected to it, while downgrading a peer stops it from being
+    /// reconnected to without affecting an existing connection.
+    ///
+    /// Returns an error if `peer_id` is not a known peer.
+    pub fn set_affinity(&self, peer_id: &PeerId, affinity: PeerAffinity) -> Result<()> {
+        match self.inner_mut().get_mut(peer_id) {
+            Some(peer_info) => {
+                peer_info.affinity = affinity;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("peer {peer_id} is not a known peer")),
+        }
+    }
+
+    /// Insert all of the provided peers, e.g. from a snapshot previously taken via
+    /// [`KnownPeers::snapshot`].
+    ///
+    /// Existing entries for a peer are replaced, so loading the same set of peers multiple times
+    /// is idempotent. Any peers with [`PeerAffinity::High`] will be dialed in the background.
+    pub fn load(&self, peers: Vec<PeerInfo>) {
+        let mut inner = self.inner_mut();
+        for peer_info in peers {
+            inner.insert(peer_info.peer_id, peer_info);
+        }
+    }
+
     fn inner(&self) -> std::sync::RwLockReadGuard<'_, HashMap<PeerId, PeerInfo>> {
         self.0.read().unwrap()
     }
@@ -750,6 +1789,121 @@ mod tests {
     use super::*;
     use std::time::{Duration, Instant};
 
+    #[test]
+    fn expired_bans_are_pruned() {
+        let banned_peers = BannedPeers::new();
+        banned_peers.ban(PeerId([1; 32]), Some(Duration::ZERO));
+        banned_peers.ban(PeerId([2; 32]), Some(Duration::ZERO));
+        banned_peers.ban(PeerId([3; 32]), None);
+
+        // Looking up an expired ban removes it
+        assert!(!banned_peers.is_banned(&PeerId([1; 32])));
+        assert!(!banned_peers
+            .0
+            .read()
+            .unwrap()
+            .contains_key(&PeerId([1; 32])));
+
+        // Banning another peer prunes every expired ban
+        banned_peers.ban(PeerId([4; 32]), Some(Duration::from_secs(60)));
+        assert!(!banned_peers
+            .0
+            .read()
+            .unwrap()
+            .contains_key(&PeerId([2; 32])));
+        assert!(banned_peers.is_banned(&PeerId([3; 32])));
+        assert!(banned_peers.is_banned(&PeerId([4; 32])));
+        assert_eq!(banned_peers.0.read().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn known_peers_with_affinity() {
+        let known_peers = KnownPeers::new();
+        let affinities = [
+            PeerAffinity::High,
+            PeerAffinity::Allowed,
+            PeerAffinity::High,
+            PeerAffinity::Never,
+            PeerAffinity::Allowed,
+            PeerAffinity::High,
+        ];
+        for (i, affinity) in affinities.into_iter().enumerate() {
+            known_peers.insert(PeerInfo {
+                peer_id: PeerId([i as u8; 32]),
+                affinity,
+                address: vec![],
+            });
+        }
+
+        for affinity in [
+            PeerAffinity::High,
+            PeerAffinity::Allowed,
+            PeerAffinity::Never,
+        ] {
+            let mut peer_ids = known_peers
+                .peers_with_affinity(affinity)
+                .into_iter()
+                .map(|peer_info| {
+                    assert_eq!(peer_info.affinity, affinity);
+                    peer_info.peer_id
+                })
+                .collect::<Vec<_>>();
+            peer_ids.sort();
+
+            let expected = affinities
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| **a == affinity)
+                .map(|(i, _)| PeerId([i as u8; 32]))
+                .collect::<Vec<_>>();
+            assert_eq!(peer_ids, expected);
+        }
+
+        assert_eq!(
+            known_peers.get(&PeerId([3; 32])).unwrap().affinity,
+            PeerAffinity::Never
+        );
+        assert!(known_peers.get(&PeerId([42; 32])).is_none());
+    }
+
+    #[test]
+    fn known_peers_snapshot_and_load() {
+        let known_peers = KnownPeers::new();
+        for (i, affinity) in [
+            PeerAffinity::High,
+            PeerAffinity::Allowed,
+            PeerAffinity::Never,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            known_peers.insert(PeerInfo {
+                peer_id: PeerId([i as u8; 32]),
+                affinity,
+                address: vec![Address::HostAndPort {
+                    host: "localhost".into(),
+                    port: 8080 + i as u16,
+                }],
+            });
+        }
+
+        let mut snapshot = known_peers.snapshot();
+        snapshot.sort_by_key(|peer_info| peer_info.peer_id);
+        let serialized = serde_json::to_string(&snapshot).unwrap();
+
+        known_peers.remove_all().for_each(drop);
+        assert!(known_peers.snapshot().is_empty());
+
+        // Loading the same snapshot multiple times doesn't duplicate entries
+        let restored: Vec<PeerInfo> = serde_json::from_str(&serialized).unwrap();
+        known_peers.load(restored.clone());
+        known_peers.load(restored);
+
+        let mut peers = known_peers.snapshot();
+        peers.sort_by_key(|peer_info| peer_info.peer_id);
+        assert_eq!(peers, snapshot);
+    }
+
     #[test]
     fn backoff() {
         // GIVEN
@@ -757,9 +1911,13 @@ mod tests {
 
         let back_off_step = Duration::from_secs(5);
         let max_back_off = Duration::from_secs(60);
+        let backoff = Backoff::Linear {
+            step: back_off_step,
+            max: max_back_off,
+        };
 
         // WHEN
-        let mut state = DialBackoffState::new(now, back_off_step, max_back_off);
+        let mut state = DialBackoffState::new(now, backoff);
 
         // THEN
         assert_eq!(state.attempts, 1);
@@ -767,7 +1925,7 @@ mod tests {
 
         // WHEN
         for attempt in 2..=12 {
-            state.update(now, back_off_step, max_back_off);
+            state.update(now, backoff);
 
             // THEN
             assert_eq!(state.attempts, attempt);
@@ -779,7 +1937,49 @@ mod tests {
 
         for attempt in 13..=15 {
             // WHEN
-            state.update(now, back_off_step, max_back_off);
+            state.update(now, backoff);
+
+            // THEN we should expect to get from now on only the max backoff
+            assert_eq!(state.attempts, attempt);
+            assert_eq!(state.backoff - now, max_back_off);
+        }
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        // GIVEN
+        let now = Instant::now();
+
+        let base_back_off = Duration::from_millis(100);
+        let max_back_off = Duration::from_secs(60);
+        let backoff = Backoff::Exponential {
+            base: base_back_off,
+            max: max_back_off,
+            jitter: 0.0,
+        };
+
+        // WHEN
+        let mut state = DialBackoffState::new(now, backoff);
+
+        // THEN
+        assert_eq!(state.attempts, 1);
+        assert_eq!(state.backoff - now, base_back_off);
+
+        // WHEN
+        for attempt in 2..=10 {
+            state.update(now, backoff);
+
+            // THEN the backoff doubles on each attempt
+            assert_eq!(state.attempts, attempt);
+            assert_eq!(
+                state.backoff - now,
+                base_back_off.saturating_mul(1 << (attempt - 1))
+            );
+        }
+
+        for attempt in 11..=40 {
+            // WHEN
+            state.update(now, backoff);
 
             // THEN we should expect to get from now on only the max backoff
             assert_eq!(state.attempts, attempt);
@@ -787,6 +1987,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn exponential_backoff_with_jitter() {
+        let now = Instant::now();
+
+        let base_back_off = Duration::from_secs(1);
+        let max_back_off = Duration::from_secs(8);
+        let backoff = Backoff::Exponential {
+            base: base_back_off,
+            max: max_back_off,
+            jitter: 0.5,
+        };
+
+        let mut state = DialBackoffState::new(now, backoff);
+        for _ in 0..4 {
+            let expected = base_back_off.saturating_mul(1 << (state.attempts - 1));
+            let delay = state.backoff - now;
+            assert!(delay >= expected);
+            assert!(delay <= expected.mul_f64(1.5));
+
+            state.update(now, backoff);
+        }
+
+        // Once the backoff is capped the jitter still spreads out the delays
+        let delays: HashSet<_> = (0..20)
+            .map(|_| {
+                state.update(now, backoff);
+                state.backoff - now
+            })
+            .collect();
+        assert!(delays
+            .iter()
+            .all(|delay| *delay >= max_back_off && *delay <= max_back_off.mul_f64(1.5)));
+        assert!(delays.len() > 1);
+    }
+
     #[tokio::test]
     async fn shutdown() {
         let socket = std::net::UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
@@ -796,9 +2031,21 @@ mod tests {
         let (connection_manager, sender) = ConnectionManager::new(
             Default::default(),
             endpoint,
-            ActivePeers::new(1),
+            Vec::new(),
+            ActivePeers::new(1, None, Default::default()),
+            Default::default(),
             Default::default(),
             echo_service(),
+            None,
+            None,
+            Vec::new().into(),
+            None,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
         );
 
         connection_manager.shutdown().await;
@@ -0,0 +1,203 @@
+use crate::{config::DatagramOverflowPolicy, PeerId};
+use bytes::Bytes;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::Notify;
+
+/// A handle to the set of subscribers interested in inbound datagrams.
+///
+/// Datagrams are delivered on a best-effort basis: if a subscriber's queue is full a datagram is
+/// dropped for that subscriber, according to its [`DatagramOverflowPolicy`], rather than applying
+/// backpressure to the connection.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DatagramSubscribers(Arc<Subscribers>);
+
+#[derive(Debug, Default)]
+struct Subscribers(Mutex<Vec<Weak<Queue>>>);
+
+impl Drop for Subscribers {
+    fn drop(&mut self) {
+        // Wake up any receivers so that they observe the network having gone away
+        for queue in self.0.get_mut().unwrap().iter().filter_map(Weak::upgrade) {
+            queue.close();
+        }
+    }
+}
+
+impl DatagramSubscribers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn subscribe(&self, capacity: usize, policy: DatagramOverflowPolicy) -> DatagramReceiver {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(QueueState {
+                datagrams: VecDeque::new(),
+                capacity,
+                policy,
+                closed: false,
+            }),
+            notify: Notify::new(),
+        });
+        (self.0).0.lock().unwrap().push(Arc::downgrade(&queue));
+        DatagramReceiver(queue)
+    }
+
+    /// Deliver a datagram to all subscribers, pruning any which have been dropped.
+    ///
+    /// Returns the number of subscribers for which a datagram had to be dropped to stay within
+    /// their capacity.
+    pub fn dispatch(&self, peer_id: PeerId, datagram: Bytes) -> usize {
+        let mut dropped = 0;
+        (self.0)
+            .0
+            .lock()
+            .unwrap()
+            .retain(|queue| match queue.upgrade() {
+                Some(queue) => {
+                    if queue.push((peer_id, datagram.clone())) {
+                        dropped += 1;
+                    }
+                    true
+                }
+                None => false,
+            });
+        dropped
+    }
+}
+
+#[derive(Debug)]
+struct Queue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+#[derive(Debug)]
+struct QueueState {
+    datagrams: VecDeque<(PeerId, Bytes)>,
+    capacity: usize,
+    policy: DatagramOverflowPolicy,
+    closed: bool,
+}
+
+impl Queue {
+    /// Enqueue a datagram, returning whether a datagram had to be dropped to make room for it.
+    fn push(&self, datagram: (PeerId, Bytes)) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let dropped = state.datagrams.len() >= state.capacity;
+        if dropped {
+            match state.policy {
+                DatagramOverflowPolicy::DropNewest => return true,
+                DatagramOverflowPolicy::DropOldest => {
+                    state.datagrams.pop_front();
+                }
+            }
+        }
+        state.datagrams.push_back(datagram);
+        drop(state);
+
+        self.notify.notify_one();
+        dropped
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+}
+
+/// Receives datagrams sent by peers via [`Peer::send_datagram`](crate::Peer::send_datagram).
+///
+/// Created via [`Network::datagrams`](crate::Network::datagrams). At most
+/// [`Config::datagram_channel_capacity`](crate::Config::datagram_channel_capacity) datagrams are
+/// buffered, beyond which datagrams are dropped according to
+/// [`Config::datagram_overflow_policy`](crate::Config::datagram_overflow_policy).
+#[derive(Debug)]
+pub struct DatagramReceiver(Arc<Queue>);
+
+impl DatagramReceiver {
+    /// Receive the next datagram along with the peer which sent it.
+    ///
+    /// Returns `None` once the network has been shut down and all buffered datagrams have been
+    /// received.
+    pub async fn recv(&mut self) -> Option<(PeerId, Bytes)> {
+        loop {
+            {
+                let mut state = self.0.state.lock().unwrap();
+                if let Some(datagram) = state.datagrams.pop_front() {
+                    return Some(datagram);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+
+            self.0.notify.notified().await;
+        }
+    }
+
+    /// Receive the next datagram if one is buffered, without waiting.
+    pub fn try_recv(&mut self) -> Option<(PeerId, Bytes)> {
+        self.0.state.lock().unwrap().datagrams.pop_front()
+    }
+
+    /// Number of datagrams which are buffered and waiting to be received.
+    pub fn len(&self) -> usize {
+        self.0.state.lock().unwrap().datagrams.len()
+    }
+
+    /// Returns `true` if no datagrams are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DatagramSubscribers;
+    use crate::{config::DatagramOverflowPolicy, PeerId};
+    use bytes::Bytes;
+
+    fn dispatch_all(subscribers: &DatagramSubscribers) -> usize {
+        (0..5u8)
+            .map(|i| subscribers.dispatch(PeerId([0; 32]), Bytes::from(vec![i])))
+            .sum()
+    }
+
+    #[test]
+    fn drop_newest() {
+        let subscribers = DatagramSubscribers::new();
+        let mut receiver = subscribers.subscribe(2, DatagramOverflowPolicy::DropNewest);
+
+        assert_eq!(dispatch_all(&subscribers), 3);
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(receiver.try_recv().unwrap().1, Bytes::from(vec![0]));
+        assert_eq!(receiver.try_recv().unwrap().1, Bytes::from(vec![1]));
+        assert!(receiver.try_recv().is_none());
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let subscribers = DatagramSubscribers::new();
+        let mut receiver = subscribers.subscribe(2, DatagramOverflowPolicy::DropOldest);
+
+        assert_eq!(dispatch_all(&subscribers), 3);
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(receiver.try_recv().unwrap().1, Bytes::from(vec![3]));
+        assert_eq!(receiver.try_recv().unwrap().1, Bytes::from(vec![4]));
+        assert!(receiver.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn closed_when_subscribers_are_dropped() {
+        let subscribers = DatagramSubscribers::new();
+        let mut receiver = subscribers.subscribe(2, DatagramOverflowPolicy::DropNewest);
+        subscribers.dispatch(PeerId([0; 32]), Bytes::new());
+
+        drop(subscribers);
+        assert!(receiver.recv().await.is_some());
+        assert!(receiver.recv().await.is_none());
+    }
+}
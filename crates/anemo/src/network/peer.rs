@@ -1,13 +1,34 @@
 use super::{
-    wire::{network_message_frame_codec, read_response, write_request},
-    OutboundRequestLayer,
+    connection_manager::{ActivePeersRef, ConnectionManagerRequest, EndpointCounters},
+    push::PUSH_ROUTE,
+    raw_stream::{RawRecvStream, RawSendStream, RAW_STREAM_ROUTE},
+    request_handler::{ORDERED_MESSAGES_ROUTE, PING_ROUTE},
+    stream::{into_chunk_stream, StreamReceiver, StreamSender},
+    wire::{encode_request, network_message_frame_codec, read_response, write_request},
+    OutboundRequestLayer, RetryPolicy,
+};
+use crate::{
+    connection::{Connection, SendStream},
+    metrics::{Metrics, RpcOutcome},
+    rpc::health::{HealthResponse, HEALTH_CHECK_ROUTE},
+    types::HeaderMap,
+    Config, PeerId, Request, Response, Result, RpcError,
 };
-use crate::{connection::Connection, Config, PeerId, Request, Response, Result};
 use bytes::Bytes;
-use futures::future::BoxFuture;
+use futures::{
+    future::{AbortHandle, Aborted, BoxFuture},
+    Future, FutureExt, SinkExt, Stream,
+};
+use quinn::RecvStream;
 use quinn_proto::ConnectionStats;
-use std::{sync::Arc, time::Duration};
-use tokio_util::codec::{FramedRead, FramedWrite};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU64,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
 use tower::{Layer, Service, ServiceExt};
 
 /// Handle to a connection with a remote Peer.
@@ -15,19 +36,35 @@ use tower::{Layer, Service, ServiceExt};
 pub struct Peer {
     connection: Connection,
     outbound_request_layer: OutboundRequestLayer,
+    default_headers: Arc<HeaderMap>,
     config: Arc<Config>,
+    metrics: Metrics,
+    active_peers: ActivePeersRef,
+    connection_manager_handle: mpsc::Sender<ConnectionManagerRequest>,
+    endpoint_counters: Arc<EndpointCounters>,
 }
 
 impl Peer {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         connection: Connection,
         outbound_request_layer: OutboundRequestLayer,
+        default_headers: Arc<HeaderMap>,
         config: Arc<Config>,
+        metrics: Metrics,
+        active_peers: ActivePeersRef,
+        connection_manager_handle: mpsc::Sender<ConnectionManagerRequest>,
+        endpoint_counters: Arc<EndpointCounters>,
     ) -> Self {
         Self {
             connection,
             outbound_request_layer,
+            default_headers,
             config,
+            metrics,
+            active_peers,
+            connection_manager_handle,
+            endpoint_counters,
         }
     }
 
@@ -35,6 +72,11 @@ impl Peer {
         self.connection.peer_id()
     }
 
+    #[cfg(test)]
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
     pub fn connection_stats(&self) -> ConnectionStats {
         self.connection.stats()
     }
@@ -43,12 +85,524 @@ impl Peer {
         self.connection.rtt()
     }
 
-    pub async fn rpc(&mut self, request: Request<Bytes>) -> Result<Response<Bytes>> {
-        self.ready().await?.call(request).await
+    /// Returns the wire protocol version negotiated with this peer when the connection was
+    /// established.
+    pub fn version(&self) -> crate::types::Version {
+        self.connection.version()
+    }
+
+    /// Returns the number of streams opened to this peer which are still open.
+    ///
+    /// This is bounded by
+    /// [`Config::max_concurrent_outbound_streams_per_peer`](crate::Config::max_concurrent_outbound_streams_per_peer),
+    /// if set.
+    pub fn outbound_streams(&self) -> usize {
+        self.connection.outbound_streams()
+    }
+
+    /// Returns the number of inbound requests from this peer which are currently being processed.
+    ///
+    /// This is bounded by
+    /// [`Config::max_concurrent_requests_per_peer`](crate::Config::max_concurrent_requests_per_peer).
+    pub fn inflight_inbound_requests(&self) -> usize {
+        self.connection.inflight_inbound_requests()
+    }
+
+    /// Returns the number of inbound requests from this peer which are waiting to be processed.
+    ///
+    /// This is bounded by
+    /// [`Config::max_queued_requests_per_peer`](crate::Config::max_queued_requests_per_peer).
+    pub fn queued_inbound_requests(&self) -> usize {
+        self.connection.queued_inbound_requests()
+    }
+
+    /// Returns the total number of streams, in either direction, opened with this peer over the
+    /// lifetime of the connection.
+    ///
+    /// Together with [`Peer::streams_closed`] this can be used to detect leaked streams: under a
+    /// steady workload the difference between the two should stay roughly constant.
+    pub fn streams_opened(&self) -> u64 {
+        self.connection.streams_opened()
+    }
+
+    /// Returns the total number of streams, in either direction, closed with this peer over the
+    /// lifetime of the connection.
+    ///
+    /// Inbound streams are counted as closed once their request has been handled, and outbound
+    /// streams once their sending half has been dropped.
+    pub fn streams_closed(&self) -> u64 {
+        self.connection.streams_closed()
+    }
+
+    /// Returns the time of the most recent request or response exchanged with this peer, or the
+    /// time the connection was established if nothing has been exchanged yet.
+    ///
+    /// Unlike keep-alives, which only keep the connection itself open, this reflects actual use of
+    /// the connection, so it can be used to find and prune peers which are connected but silent.
+    pub fn last_activity(&self) -> std::time::Instant {
+        self.connection.last_activity()
+    }
+
+    /// Returns a snapshot of the key/value metadata attached to this peer.
+    ///
+    /// Metadata is stored alongside the connection, so it is shared by all handles to this peer
+    /// and dropped once the peer disconnects. See [`Peer::set_metadata`].
+    pub fn metadata(&self) -> HashMap<String, String> {
+        self.connection.metadata()
+    }
+
+    /// Attach `value` to this peer under `key`, e.g. to tag it with its role or region, replacing
+    /// any value previously set for `key`.
+    pub fn set_metadata<K: Into<String>, V: Into<String>>(&self, key: K, value: V) {
+        self.connection.set_metadata(key.into(), value.into())
+    }
+
+    /// Returns the DER encoded certificate this peer presented during the TLS handshake, e.g. to
+    /// audit it or to authorize the peer based on extensions carried by the certificate.
+    ///
+    /// The [`PeerId`] is derived from the public key in this certificate.
+    pub fn peer_certificate(&self) -> Option<Vec<u8>> {
+        Some(self.connection.peer_certificate().to_vec())
+    }
+
+    /// Returns the routes this peer advertised when the connection was established.
+    ///
+    /// Peers advertise the routes registered with their [`Router`](crate::Router), or those
+    /// provided via [`Builder::capabilities`](crate::Builder::capabilities). The set is empty if
+    /// the peer advertised no routes, e.g. because it's running an older version.
+    pub fn capabilities(&self) -> &HashSet<String> {
+        self.connection.capabilities()
+    }
+
+    /// Returns whether this peer advertised a route matching `route`.
+    ///
+    /// This can be used to fail fast rather than sending a request which the peer can't serve.
+    /// Advertised routes may include parameters (`/:name`) and catch-alls (`/*name`), which match
+    /// `route` the same way they do in a [`Router`](crate::Router).
+    pub fn supports_route(&self, route: &str) -> bool {
+        self.capabilities()
+            .iter()
+            .any(|pattern| route_matches(pattern, route))
+    }
+
+    /// Returns the limit, in bytes per second, on the data sent to this peer, if any.
+    ///
+    /// This defaults to
+    /// [`Config::max_outbound_bytes_per_second_per_peer`](crate::Config::max_outbound_bytes_per_second_per_peer)
+    /// and can be changed via [`Peer::set_outbound_bandwidth_limit`].
+    pub fn outbound_bandwidth_limit(&self) -> Option<u64> {
+        self.connection.outbound_bandwidth_limit()
+    }
+
+    /// Limit the data sent to this peer to `bytes_per_second`, or remove the limit if `None`.
+    ///
+    /// The limit applies to all streams sent to this peer, including responses to its requests,
+    /// and is shared by all handles to this peer. Once the budget is exhausted writes wait for it
+    /// to be replenished rather than failing. Up to one second worth of bytes can be sent in a
+    /// burst.
+    pub fn set_outbound_bandwidth_limit(&self, bytes_per_second: Option<NonZeroU64>) {
+        self.connection
+            .set_outbound_bandwidth_limit(bytes_per_second.map(NonZeroU64::get))
+    }
+
+    /// Returns the total number of bytes written to streams sent to this peer, including framing.
+    pub fn outbound_bytes_sent(&self) -> u64 {
+        self.connection.outbound_bytes_sent()
+    }
+
+    /// Returns a snapshot of the health of the connection with this peer.
+    pub fn stats(&self) -> crate::types::ConnectionStats {
+        crate::types::ConnectionStats {
+            resumed: self.connection.resumed(),
+            ..self.connection_stats().into()
+        }
+    }
+
+    pub async fn rpc(&mut self, request: Request<Bytes>) -> Result<Response<Bytes>, RpcError> {
+        self.ready().await?.call(request).await.map_err(Into::into)
+    }
+
+    /// Perform an RPC which is bounded by the provided `timeout`.
+    ///
+    /// The timeout is carried in the request's [`TIMEOUT`](crate::types::header::TIMEOUT) header,
+    /// so the peer is also made aware of how long it has to respond. If the shorter of this timeout
+    /// and any configured outbound request timeout expires, the underlying stream is reset and a
+    /// [`RpcError::Timeout`] error is returned.
+    pub async fn rpc_with_timeout(
+        &self,
+        request: Request<Bytes>,
+        timeout: Duration,
+    ) -> Result<Response<Bytes>, RpcError> {
+        self.clone().rpc(request.with_timeout(timeout)).await
+    }
+
+    /// Perform an RPC which must complete by the provided `deadline`.
+    ///
+    /// The time remaining until the deadline is sent as the request's timeout, from which the
+    /// peer's handler learns the deadline via [`Request::deadline`] and can stop working on the
+    /// request once it is no longer of use. See [`Peer::rpc_with_timeout`] for more details.
+    pub async fn rpc_with_deadline(
+        &self,
+        request: Request<Bytes>,
+        deadline: Instant,
+    ) -> Result<Response<Bytes>, RpcError> {
+        self.rpc_with_timeout(request, deadline.saturating_duration_since(Instant::now()))
+            .await
+    }
+
+    /// Perform an RPC which can be cancelled via the returned [`AbortHandle`].
+    ///
+    /// Aborting the RPC resets its stream, which the peer observes as the RPC being cancelled so
+    /// that it can stop processing the request, and the returned future resolves with
+    /// [`RpcError::Cancelled`]. Dropping the future cancels the RPC in the same way.
+    pub fn rpc_abortable(
+        &self,
+        request: Request<Bytes>,
+    ) -> (
+        impl Future<Output = Result<Response<Bytes>, RpcError>>,
+        AbortHandle,
+    ) {
+        let mut peer = self.clone();
+        let (rpc, handle) = futures::future::abortable(async move { peer.rpc(request).await });
+        let rpc = rpc.map(|result| match result {
+            Ok(result) => result,
+            Err(Aborted) => Err(RpcError::Cancelled),
+        });
+
+        (rpc, handle)
+    }
+
+    /// Perform an RPC, retrying it according to `policy` if it fails due to a transient transport
+    /// error.
+    ///
+    /// Only [`RpcError::ConnectionLost`], [`RpcError::Timeout`] and [`RpcError::RemoteClosed`]
+    /// errors are retried; a response is returned as-is regardless of its status. Since the peer
+    /// may have processed the request even though an attempt failed, the request must be marked
+    /// as idempotent via [`Request::set_idempotent`]. Each attempt is made on a fresh stream and,
+    /// if the connection was lost, the peer is first redialed at the address the connection was
+    /// established with.
+    pub async fn rpc_with_retry(
+        &self,
+        request: Request<Bytes>,
+        policy: RetryPolicy,
+    ) -> Result<Response<Bytes>, RpcError> {
+        if !request.is_idempotent() {
+            return Err(RpcError::Other(anyhow::anyhow!(
+                "retried requests must be marked as idempotent"
+            )));
+        }
+
+        let mut peer = self.clone();
+        let mut attempt = 1;
+
+        loop {
+            let error = match peer.rpc(request.duplicate()).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+
+            if attempt >= policy.max_attempts || !RetryPolicy::is_retryable(&error) {
+                return Err(error);
+            }
+
+            let backoff = policy.backoff(attempt);
+            tracing::debug!(
+                peer_id = %peer.peer_id(),
+                attempt,
+                "rpc failed, retrying in {backoff:?}: {error}"
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+
+            if peer.connection.close_reason().is_some() {
+                // If reconnecting fails the next attempt fails as well, counting towards the
+                // maximum number of attempts
+                if let Err(e) = peer.reconnect().await {
+                    tracing::debug!(peer_id = %peer.peer_id(), "unable to reconnect: {e}");
+                }
+            }
+        }
+    }
+
+    /// Replace this peer's closed connection with a new one, dialing the peer if there isn't
+    /// already another connection with it.
+    async fn reconnect(&mut self) -> Result<()> {
+        let peer_id = self.peer_id();
+        let active_peers = || {
+            self.active_peers
+                .upgrade()
+                .ok_or_else(|| anyhow::anyhow!("network has been shutdown"))
+        };
+
+        if active_peers()?.get(&peer_id).is_none() {
+            let (sender, receiver) = oneshot::channel();
+            self.connection_manager_handle
+                .send(ConnectionManagerRequest::ConnectRequest(
+                    self.connection.remote_address().into(),
+                    Some(peer_id),
+                    sender,
+                ))
+                .await
+                .map_err(|_| anyhow::anyhow!("network has been shutdown"))?;
+            receiver.await??;
+        }
+
+        self.connection = active_peers()?
+            .get(&peer_id)
+            .ok_or(RpcError::NotConnected(peer_id))?;
+        Ok(())
+    }
+
+    /// Measure the round-trip time to this peer at the application layer.
+    ///
+    /// Unlike [`Peer::connection_rtt`], which is QUIC's estimate of the network round-trip time,
+    /// this includes the time taken to open a stream and for the peer to schedule and respond to
+    /// the request. Pings are handled by a built-in handler so they do not require any support
+    /// from the peer's service.
+    pub async fn ping(&self) -> Result<Duration> {
+        let request = Request::new(Bytes::new()).with_route(PING_ROUTE);
+        let start = Instant::now();
+        let response = self.clone().rpc(request).await?;
+        let rtt = start.elapsed();
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "ping failed with status: {}",
+                response.status()
+            ));
+        }
+
+        Ok(rtt)
+    }
+
+    /// Check on the health of this peer.
+    ///
+    /// The peer must be serving a [`HealthService`](crate::rpc::health::HealthService) in order
+    /// to respond to health checks.
+    pub async fn check_health(&self) -> Result<HealthResponse> {
+        let request = Request::new(Bytes::new()).with_route(HEALTH_CHECK_ROUTE);
+        let response = self.clone().rpc(request).await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "health check failed with status: {}",
+                response.status()
+            ));
+        }
+
+        bincode::deserialize(response.body()).map_err(Into::into)
+    }
+
+    /// Send a one-way message to this peer.
+    ///
+    /// The message is written to a new uni-directional stream using the same framing as
+    /// [`Peer::rpc`]. This returns once the stream has been finished and acknowledged by the peer
+    /// without waiting for the peer's service to process the message. Any response produced by
+    /// the peer's service is discarded.
+    pub async fn message(&self, mut request: Request<Bytes>) -> Result<()> {
+        self.apply_default_headers(&mut request);
+        let send_stream = self.open_uni_stream().await?;
+        let mut send_stream =
+            FramedWrite::new(send_stream, network_message_frame_codec(&self.config));
+
+        self.metrics.bytes_sent(request.body().len());
+        write_request(&mut send_stream, request, self.config.compression()).await?;
+        send_stream.get_mut().finish().await?;
+
+        Ok(())
+    }
+
+    /// Push an unsolicited message to this peer, outside of the request/response flow.
+    ///
+    /// Pushes are delivered to the receivers returned by the peer's
+    /// [`Network::incoming_pushes`](crate::Network::incoming_pushes) rather than to its service,
+    /// so that e.g. a handler can notify a peer of changes it subscribed to without the peer
+    /// having to poll. Like [`Peer::message`], the push is written to a new uni-directional stream
+    /// and no response is expected.
+    pub async fn push(&self, message: Bytes) -> Result<()> {
+        self.message(Request::new(message).with_route(PUSH_ROUTE))
+            .await
+    }
+
+    /// Create a handle for sending one-way messages to this peer which are handled in the order
+    /// they were sent.
+    ///
+    /// Messages sent via [`Peer::message`] each use their own stream and so may be handled by the
+    /// peer in any order. An [`OrderedSender`] instead sends all of its messages over a single
+    /// uni-directional stream, which the peer reads and passes to its service one at a time.
+    ///
+    /// This comes at the cost of head-of-line blocking: a message which is lost in transit delays
+    /// all messages sent after it until it has been retransmitted, and a message which is slow to
+    /// be handled by the peer delays the handling of all messages after it. Only messages sent via
+    /// the same `OrderedSender` are ordered with respect to each other.
+    pub fn ordered_sender(&self) -> OrderedSender {
+        OrderedSender {
+            peer: self.clone(),
+            send_stream: None,
+        }
+    }
+
+    /// Send an unreliable datagram to this peer.
+    ///
+    /// Datagrams bypass stream framing and the peer's service entirely and are instead delivered
+    /// to subscribers of [`Network::datagrams`](crate::Network::datagrams). Delivery is
+    /// best-effort: datagrams may be lost, duplicated or arrive out of order.
+    ///
+    /// Returns an error if `data` exceeds the maximum datagram size negotiated with the peer.
+    pub fn send_datagram(&self, data: Bytes) -> Result<()> {
+        let max_datagram_size = self
+            .connection
+            .max_datagram_size()
+            .ok_or_else(|| anyhow::anyhow!("datagrams are not supported by peer"))?;
+
+        if data.len() > max_datagram_size {
+            return Err(anyhow::anyhow!(
+                "datagram of {} bytes exceeds the maximum datagram size of {} bytes",
+                data.len(),
+                max_datagram_size,
+            ));
+        }
+
+        self.connection.send_datagram(data)?;
+        self.endpoint_counters.datagram_sent();
+
+        Ok(())
+    }
+
+    /// Start a bi-directional streaming RPC with this peer.
+    ///
+    /// The provided `request` is sent to the peer, after which additional frames can be sent via
+    /// the returned [`StreamSender`] and frames sent back by the peer can be read via the returned
+    /// [`StreamReceiver`]. Both halves are backed by a single QUIC stream and can be closed
+    /// independently.
+    ///
+    /// The peer must have configured a handler via
+    /// [`Builder::stream_handler`](crate::Builder::stream_handler) in order to accept streaming
+    /// RPCs.
+    pub async fn rpc_stream(
+        &self,
+        mut request: Request<Bytes>,
+    ) -> Result<(StreamSender, StreamReceiver)> {
+        self.apply_default_headers(&mut request);
+        request
+            .headers_mut()
+            .insert(crate::types::header::STREAMING.into(), "true".into());
+
+        let (send_stream, recv_stream) = self.open_bi_stream().await?;
+        set_stream_priority(&send_stream, request.priority());
+        let mut send_stream =
+            FramedWrite::new(send_stream, network_message_frame_codec(&self.config));
+        let recv_stream = FramedRead::new(recv_stream, network_message_frame_codec(&self.config));
+
+        write_request(&mut send_stream, request, self.config.compression()).await?;
+
+        Ok((
+            StreamSender::new(send_stream),
+            StreamReceiver::new(recv_stream),
+        ))
+    }
+
+    /// Start a server-streaming RPC with this peer, for responses which are too large to buffer.
+    ///
+    /// The provided `request` is sent to the peer, which responds with a sequence of chunks that
+    /// are read incrementally from the returned stream. The stream ends once the peer has sent all
+    /// of its chunks. If the RPC fails part way through, e.g. because the peer reset the stream or
+    /// the connection was lost, the stream yields a final error.
+    ///
+    /// The peer must have configured a handler via
+    /// [`Builder::server_stream_handler`](crate::Builder::server_stream_handler) in order to
+    /// accept server-streaming RPCs.
+    pub async fn rpc_server_stream(
+        &self,
+        mut request: Request<Bytes>,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        request
+            .headers_mut()
+            .insert(crate::types::header::SERVER_STREAMING.into(), "true".into());
+
+        let (sender, receiver) = self.rpc_stream(request).await?;
+        // Nothing else is sent to the peer after the initial request. The peer may stop reading
+        // its side of the stream as soon as it has read the request, so failing to finish isn't
+        // an error; any actual failure is surfaced when reading the response.
+        let _ = sender.finish().await;
+
+        Ok(into_chunk_stream(receiver))
+    }
+
+    /// Open a raw bi-directional stream with this peer.
+    ///
+    /// Raw streams carry bytes as-is, allowing applications to run protocols with their own
+    /// framing while reusing this connection. Streams are delivered to subscribers of the peer's
+    /// [`Network::accept_raw_streams`](crate::Network::accept_raw_streams) rather than to its
+    /// service, and are multiplexed alongside RPCs without affecting them.
+    pub async fn open_bi(&self) -> Result<(RawSendStream, RawRecvStream)> {
+        let (send_stream, recv_stream) = self.open_bi_stream().await?;
+        let mut send_stream =
+            FramedWrite::new(send_stream, network_message_frame_codec(&self.config));
+
+        // Raw streams are identified by a request header carrying a reserved route
+        let request = Request::new(Bytes::new()).with_route(RAW_STREAM_ROUTE);
+        write_request(&mut send_stream, request, None).await?;
+
+        Ok((
+            RawSendStream::new(send_stream.into_inner()),
+            RawRecvStream::new(recv_stream),
+        ))
+    }
+
+    /// Reserve a slot for a new outbound stream, which is released once the guard is dropped.
+    fn reserve_outbound_stream(&self) -> Result<crate::connection::CounterGuard, RpcError> {
+        self.connection
+            .reserve_outbound_stream(self.config.max_concurrent_outbound_streams_per_peer())
+            .ok_or_else(|| {
+                tracing::debug!(
+                    peer_id =% self.peer_id(),
+                    outbound_streams = self.outbound_streams(),
+                    "refusing to open stream due to too many concurrent outbound streams"
+                );
+                RpcError::TooManyStreams(self.peer_id())
+            })
+    }
+
+    /// Add any of the network's default headers which aren't already set on `request`.
+    fn apply_default_headers(&self, request: &mut Request<Bytes>) {
+        for (key, value) in self.default_headers.iter() {
+            if !request.headers().contains_key(key) {
+                request.headers_mut().insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Open a uni-directional stream which counts towards the outbound stream limit until it's
+    /// dropped.
+    async fn open_uni_stream(&self) -> Result<SendStream, RpcError> {
+        let guard = self.reserve_outbound_stream()?;
+        let send_stream = self.connection.open_uni().await?;
+        Ok(send_stream.with_guard(guard))
     }
 
-    async fn do_rpc(&self, request: Request<Bytes>) -> Result<Response<Bytes>> {
+    /// Open a bi-directional stream which counts towards the outbound stream limit until its
+    /// sending half is dropped.
+    async fn open_bi_stream(&self) -> Result<(SendStream, RecvStream), RpcError> {
+        let guard = self.reserve_outbound_stream()?;
         let (send_stream, recv_stream) = self.connection.open_bi().await?;
+        Ok((send_stream.with_guard(guard), recv_stream))
+    }
+
+    #[tracing::instrument(
+        name = "rpc",
+        level = "debug",
+        skip_all,
+        fields(
+            peer_id = %self.peer_id(),
+            remote_addr = %self.connection.remote_address(),
+            stream_id = tracing::field::Empty,
+        )
+    )]
+    async fn do_rpc(&self, request: Request<Bytes>) -> Result<Response<Bytes>, RpcError> {
+        let (send_stream, recv_stream) = self.open_bi_stream().await?;
+        tracing::Span::current().record("stream_id", tracing::field::display(send_stream.id()));
+        set_stream_priority(&send_stream, request.priority());
         let mut send_stream =
             FramedWrite::new(send_stream, network_message_frame_codec(&self.config));
         let mut recv_stream =
@@ -58,14 +612,21 @@ impl Peer {
         // Write Request
         //
 
-        write_request(&mut send_stream, request).await?;
+        self.metrics.bytes_sent(request.body().len());
+        write_request(&mut send_stream, request, self.config.compression())
+            .await
+            .map_err(RpcError::from_stream_error)?;
         send_stream.get_mut().finish().await?;
 
         //
         // Read Response
         //
 
-        let mut response = read_response(&mut recv_stream).await?;
+        let mut response = read_response(&mut recv_stream)
+            .await
+            .map_err(RpcError::from_stream_error)?;
+        self.connection.record_activity();
+        self.metrics.bytes_received(response.body().len());
 
         // Set the PeerId of this peer
         response.extensions_mut().insert(self.peer_id());
@@ -94,15 +655,121 @@ impl Service<Request<Bytes>> for Peer {
         // * Direction of the Request
         request.extensions_mut().insert(self.peer_id());
         request.extensions_mut().insert(crate::Direction::Outbound);
+        self.apply_default_headers(&mut request);
 
         let peer = self.clone();
         let inner = tower::service_fn(move |request| {
             let peer = peer.clone();
-            async move { peer.do_rpc(request).await }
+            async move { peer.do_rpc(request).await.map_err(crate::Error::from) }
         })
         .boxed();
 
+        let peer_id = self.peer_id();
+        let route = request.route().to_owned();
         let mut service = self.outbound_request_layer.layer(inner);
-        service.call(request)
+        let response = service.call(request);
+        let metrics = self.metrics.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let result = response.await;
+            metrics.rpc_completed(
+                peer_id,
+                crate::Direction::Outbound,
+                &route,
+                RpcOutcome::from_outbound_result(&result),
+                start.elapsed(),
+            );
+            result
+        })
+    }
+}
+
+/// A handle for sending one-way messages to a peer which are handled in the order they were sent.
+///
+/// Created via [`Peer::ordered_sender`]. The underlying stream is opened when the first message is
+/// sent and is held open, counting towards
+/// [`Config::max_concurrent_outbound_streams_per_peer`](crate::Config::max_concurrent_outbound_streams_per_peer),
+/// until [`OrderedSender::finish`] is called or the sender is dropped. Dropping an `OrderedSender`
+/// without calling [`OrderedSender::finish`] resets the stream, which can cause messages the peer
+/// hasn't yet received to be discarded.
+pub struct OrderedSender {
+    peer: Peer,
+    send_stream: Option<FramedWrite<SendStream, LengthDelimitedCodec>>,
+}
+
+impl OrderedSender {
+    /// Send a one-way message to the peer.
+    ///
+    /// This returns once the message has been written to the stream, without waiting for the peer
+    /// to receive it. If sending fails the stream is abandoned and the next message is sent on a
+    /// new stream, in which case messages sent before and after the failure are not guaranteed
+    /// to be handled in order.
+    pub async fn send(&mut self, request: Request<Bytes>) -> Result<()> {
+        let result = self.try_send(request).await;
+        if result.is_err() {
+            self.send_stream = None;
+        }
+        result
+    }
+
+    async fn try_send(&mut self, mut request: Request<Bytes>) -> Result<()> {
+        self.peer.apply_default_headers(&mut request);
+        if self.send_stream.is_none() {
+            let send_stream = self.peer.open_uni_stream().await?;
+            let mut send_stream =
+                FramedWrite::new(send_stream, network_message_frame_codec(&self.peer.config));
+            // The stream is marked as carrying ordered messages by an initial request
+            let request = Request::new(Bytes::new()).with_route(ORDERED_MESSAGES_ROUTE);
+            write_request(&mut send_stream, request, None).await?;
+            self.send_stream = Some(send_stream);
+        }
+        let send_stream = self.send_stream.as_mut().expect("stream was just opened");
+
+        self.peer.metrics.bytes_sent(request.body().len());
+        let message = encode_request(
+            request,
+            network_message_frame_codec(&self.peer.config),
+            self.peer.config.compression(),
+        )
+        .await?;
+        send_stream.send(message).await?;
+
+        Ok(())
+    }
+
+    /// Gracefully close the underlying stream, returning once the peer has received all of the
+    /// messages sent.
+    pub async fn finish(mut self) -> Result<()> {
+        if let Some(mut send_stream) = self.send_stream.take() {
+            SinkExt::<Bytes>::flush(&mut send_stream).await?;
+            send_stream.get_mut().finish().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `route` matches the route `pattern`, which may contain parameters and
+/// catch-alls.
+fn route_matches(pattern: &str, route: &str) -> bool {
+    let mut route_segments = route.split('/');
+    for pattern_segment in pattern.split('/') {
+        if pattern_segment.starts_with('*') {
+            return true;
+        }
+        match route_segments.next() {
+            Some(segment) if pattern_segment.starts_with(':') && !segment.is_empty() => {}
+            Some(segment) if segment == pattern_segment => {}
+            _ => return false,
+        }
+    }
+    route_segments.next().is_none()
+}
+
+/// Apply a request's priority hint to the stream it's sent on.
+fn set_stream_priority(send_stream: &SendStream, priority: i32) {
+    if priority != 0 {
+        // This can only fail if the stream has already been closed, in which case writing the
+        // request fails anyway
+        let _ = send_stream.set_priority(priority);
     }
 }
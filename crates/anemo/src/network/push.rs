@@ -0,0 +1,45 @@
+use crate::PeerId;
+use bytes::Bytes;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Route used to open a uni-directional stream carrying a message pushed via
+/// [`Peer::push`](crate::Peer::push). Pushes bypass the service and are instead handed to
+/// subscribers of [`Network::incoming_pushes`](crate::Network::incoming_pushes).
+pub(crate) const PUSH_ROUTE: &str = "/anemo.Push";
+
+/// A handle to the set of subscribers interested in messages pushed by peers.
+///
+/// Unlike datagrams, pushes are delivered reliably: if a subscriber's channel is full, delivery
+/// waits for room, applying backpressure to the peer via the stream's flow control.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PushSubscribers(Arc<Mutex<Vec<PushSender>>>);
+
+type PushSender = mpsc::Sender<(PeerId, Bytes)>;
+
+impl PushSubscribers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn subscribe(&self, capacity: usize) -> mpsc::Receiver<(PeerId, Bytes)> {
+        let (sender, receiver) = mpsc::channel(capacity);
+        self.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Deliver a pushed message to all subscribers, pruning any which have been dropped.
+    ///
+    /// The message is dropped if there are no subscribers.
+    pub async fn dispatch(&self, peer_id: PeerId, message: Bytes) {
+        let subscribers = self.0.lock().unwrap().clone();
+        for subscriber in subscribers {
+            let _ = subscriber.send((peer_id, message.clone())).await;
+        }
+
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|subscriber| !subscriber.is_closed());
+    }
+}
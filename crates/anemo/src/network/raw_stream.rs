@@ -0,0 +1,148 @@
+use crate::{connection::SendStream, PeerId, Result};
+use bytes::{Buf, Bytes};
+use quinn::RecvStream;
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc,
+};
+use tokio_util::codec::{FramedRead, LengthDelimitedCodec};
+
+/// Route used to open a raw stream. Streams opened with this route bypass the request handler and
+/// are instead handed to subscribers of
+/// [`Network::accept_raw_streams`](crate::Network::accept_raw_streams).
+pub(crate) const RAW_STREAM_ROUTE: &str = "/anemo.RawStream";
+
+/// An inbound raw stream, along with the peer which opened it.
+pub type InboundRawStream = (PeerId, RawSendStream, RawRecvStream);
+
+/// The sending half of a raw bi-directional stream.
+///
+/// Bytes written to a `RawSendStream` are sent to the peer as-is, without any framing. Dropping a
+/// `RawSendStream` without calling [`RawSendStream::finish`] resets the stream, signaling to the
+/// remote side that the stream was aborted.
+pub struct RawSendStream(SendStream);
+
+impl RawSendStream {
+    pub(crate) fn new(send_stream: SendStream) -> Self {
+        Self(send_stream)
+    }
+
+    /// Gracefully close this half of the stream, waiting for all written data to be acknowledged
+    /// by the peer.
+    pub async fn finish(mut self) -> Result<()> {
+        self.0.finish().await.map_err(Into::into)
+    }
+}
+
+impl AsyncWrite for RawSendStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// The receiving half of a raw bi-directional stream.
+pub struct RawRecvStream {
+    // Bytes which were read from the stream while reading the stream's request header
+    buffered: Bytes,
+    recv_stream: RecvStream,
+}
+
+impl RawRecvStream {
+    pub(crate) fn new(recv_stream: RecvStream) -> Self {
+        Self {
+            buffered: Bytes::new(),
+            recv_stream,
+        }
+    }
+
+    /// Take over a stream which has been read from via `FramedRead`, preserving any bytes which
+    /// have been buffered but not yet decoded.
+    pub(crate) fn from_framed(
+        mut recv_stream: FramedRead<RecvStream, LengthDelimitedCodec>,
+    ) -> Self {
+        let buffered = recv_stream.read_buffer_mut().split().freeze();
+        Self {
+            buffered,
+            recv_stream: recv_stream.into_inner(),
+        }
+    }
+}
+
+impl AsyncRead for RawRecvStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if !self.buffered.is_empty() {
+            let len = std::cmp::min(self.buffered.len(), buf.remaining());
+            buf.put_slice(&self.buffered[..len]);
+            self.buffered.advance(len);
+            return Poll::Ready(Ok(()));
+        }
+
+        Pin::new(&mut self.recv_stream).poll_read(cx, buf)
+    }
+}
+
+/// A handle to the set of subscribers interested in inbound raw streams.
+///
+/// Unlike datagrams, a stream can only be handed to a single subscriber so each stream is given to
+/// the first subscriber with room in its channel. Streams which no subscriber has room for are
+/// dropped, resetting them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RawStreamSubscribers(Arc<Mutex<Vec<mpsc::Sender<InboundRawStream>>>>);
+
+impl RawStreamSubscribers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn subscribe(&self, capacity: usize) -> mpsc::Receiver<InboundRawStream> {
+        let (sender, receiver) = mpsc::channel(capacity);
+        self.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Deliver a stream to a subscriber, pruning any which have been dropped.
+    pub fn dispatch(&self, stream: InboundRawStream) {
+        let mut stream = Some(stream);
+        self.0.lock().unwrap().retain(|sender| match stream.take() {
+            Some(s) => match sender.try_send(s) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(s)) => {
+                    stream = Some(s);
+                    true
+                }
+                Err(mpsc::error::TrySendError::Closed(s)) => {
+                    stream = Some(s);
+                    false
+                }
+            },
+            // The stream has already been delivered
+            None => true,
+        });
+
+        if let Some((peer_id, _, _)) = stream {
+            tracing::debug!(peer_id =% peer_id, "dropping raw stream with no available subscriber");
+        }
+    }
+}
@@ -1,119 +1,284 @@
 use super::{
-    wire::{network_message_frame_codec, read_request, write_response},
+    connection_manager::EndpointCounters,
+    datagram::DatagramSubscribers,
+    push::{PushSubscribers, PUSH_ROUTE},
+    raw_stream::{RawRecvStream, RawSendStream, RawStreamSubscribers, RAW_STREAM_ROUTE},
+    scheduler::RequestScheduler,
+    stream::{StreamHandler, StreamReceiver, StreamSender},
+    wire::{decode_request, network_message_frame_codec, read_request, write_response},
     ActivePeers,
 };
 use crate::{
+    config::CompressionConfig,
     connection::{Connection, SendStream},
+    metrics::{Metrics, RpcOutcome},
+    types::response::ApplicationErrorCode,
     Config, Request, Response, Result,
 };
 use bytes::Bytes;
+use futures::{Future, StreamExt};
 use quinn::RecvStream;
 use std::convert::Infallible;
-use std::sync::Arc;
-use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{watch, Semaphore},
+    task::JoinSet,
+};
+use tokio_util::{
+    codec::{FramedRead, FramedWrite, LengthDelimitedCodec},
+    sync::CancellationToken,
+};
 use tower::{util::BoxCloneService, ServiceExt};
-use tracing::{debug, trace};
+use tracing::{debug, debug_span, instrument, trace, Instrument, Span};
+
+/// Route of the built-in handler for [`Peer::ping`](crate::Peer::ping), which is served regardless
+/// of the configured service.
+pub(crate) const PING_ROUTE: &str = "/anemo.Ping/Ping";
+
+/// Route used to open a uni-directional stream carrying a sequence of messages sent via an
+/// [`OrderedSender`](crate::OrderedSender). Each subsequent frame on the stream is a whole
+/// message, which are handled one at a time in the order they were sent.
+pub(crate) const ORDERED_MESSAGES_ROUTE: &str = "/anemo.OrderedMessages";
 
 /// Manages incoming requests from a peer.
 ///
-/// Currently only bi-directional streams are supported. Whenever a new request (stream) is
-/// received a new task is spawn to handle it.
+/// Requests arrive either on bi-directional streams (RPCs) or uni-directional streams (messages).
+/// Whenever a new request (stream) is received a new task is spawn to handle it.
 pub(crate) struct InboundRequestHandler {
     config: Arc<Config>,
     connection: Connection,
 
     service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+    stream_handler: Option<StreamHandler>,
     active_peers: ActivePeers,
+    datagram_subscribers: DatagramSubscribers,
+    push_subscribers: PushSubscribers,
+    raw_stream_subscribers: RawStreamSubscribers,
+    request_scheduler: Option<RequestScheduler>,
+    endpoint_counters: Arc<EndpointCounters>,
+
+    /// Signals that the network is shutting down and that no new requests should be accepted.
+    draining: watch::Receiver<bool>,
+
+    metrics: Metrics,
 }
 
 impl InboundRequestHandler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<Config>,
         connection: Connection,
         service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+        stream_handler: Option<StreamHandler>,
         active_peers: ActivePeers,
+        datagram_subscribers: DatagramSubscribers,
+        push_subscribers: PushSubscribers,
+        raw_stream_subscribers: RawStreamSubscribers,
+        request_scheduler: Option<RequestScheduler>,
+        endpoint_counters: Arc<EndpointCounters>,
+        draining: watch::Receiver<bool>,
+        metrics: Metrics,
     ) -> Self {
         Self {
             config,
             connection,
             service,
+            stream_handler,
             active_peers,
+            datagram_subscribers,
+            push_subscribers,
+            raw_stream_subscribers,
+            request_scheduler,
+            endpoint_counters,
+            draining,
+            metrics,
         }
     }
 
-    pub async fn start(self) {
+    // Span fields are limited to public identifiers; the connection's key material is never
+    // logged.
+    #[instrument(
+        name = "connection",
+        level = "debug",
+        skip_all,
+        fields(
+            peer_id = %self.connection.peer_id(),
+            remote_addr = %self.connection.remote_address(),
+            origin = %self.connection.origin(),
+        )
+    )]
+    pub async fn start(mut self) {
         debug!(peer =% self.connection.peer_id(), "InboundRequestHandler started");
 
-        let mut inflight_requests = tokio::task::JoinSet::new();
+        let mut inflight_requests = JoinSet::new();
+        let request_limiter = Arc::new(Semaphore::new(
+            self.config.max_concurrent_requests_per_peer(),
+        ));
+        // Once this many requests are in-flight or queued, stop accepting new streams so that
+        // QUIC's flow control applies backpressure to the peer
+        let max_outstanding_requests = self.config.max_concurrent_requests_per_peer()
+            + self.config.max_queued_requests_per_peer();
 
+        // `None` indicates that we stopped accepting requests because the network is draining
         let close_reason = loop {
+            if *self.draining.borrow() {
+                break None;
+            }
+
+            let accepting = inflight_requests.len() < max_outstanding_requests;
+
             tokio::select! {
-                // anemo does not currently use uni streams so we can
-                // just ignore and drop the stream
-                uni = self.connection.accept_uni() => {
+                Ok(()) = self.draining.changed() => {},
+                uni = self.connection.accept_uni(), if accepting => {
                     match uni {
-                        Ok(recv_stream) => trace!("incoming uni stream! {}", recv_stream.id()),
+                        Ok(recv_stream) => {
+                            trace!("incoming uni stream! {}", recv_stream.id());
+                            let span = debug_span!("message", stream_id = %recv_stream.id());
+                            let request_handler =
+                                UniStreamRequestHandler::new(&self.config, self.connection.clone(), self.service.clone(), self.push_subscribers.clone(), self.metrics.clone(), recv_stream);
+                            self.spawn_request(&mut inflight_requests, &request_limiter, request_handler.handle(), span);
+                        }
                         Err(e) => {
                             trace!("error listening for incoming uni streams: {e}");
-                            break e;
+                            break Some(e);
                         }
                     }
                 },
-                bi = self.connection.accept_bi() => {
+                bi = self.connection.accept_bi(), if accepting => {
                     match bi {
                         Ok((bi_tx, bi_rx)) => {
                             trace!("incoming bi stream! {}", bi_tx.id());
+                            let span = debug_span!("rpc", stream_id = %bi_tx.id());
                             let request_handler =
-                                BiStreamRequestHandler::new(&self.config, self.connection.clone(), self.service.clone(), bi_tx, bi_rx);
-                            inflight_requests.spawn(request_handler.handle());
+                                BiStreamRequestHandler::new(&self.config, self.connection.clone(), self.service.clone(), self.stream_handler.clone(), self.raw_stream_subscribers.clone(), self.metrics.clone(), bi_tx, bi_rx);
+                            self.spawn_request(&mut inflight_requests, &request_limiter, request_handler.handle(), span);
                         }
                         Err(e) => {
                             trace!("error listening for incoming bi streams: {e}");
-                            break e;
+                            break Some(e);
                         }
                     }
                 },
-                // anemo does not currently use datagrams so we can
-                // just ignore them
                 datagram = self.connection.read_datagram() => {
                     match datagram {
-                        Ok(datagram) => trace!("incoming datagram of length: {}", datagram.len()),
+                        Ok(datagram) => {
+                            trace!("incoming datagram of length: {}", datagram.len());
+                            self.endpoint_counters.datagram_received();
+                            let dropped = self
+                                .datagram_subscribers
+                                .dispatch(self.connection.peer_id(), datagram);
+                            self.endpoint_counters.datagrams_dropped(dropped);
+                        }
                         Err(e) => {
                             trace!("error listening for datagrams: {e}");
-                            break e;
+                            break Some(e);
                         }
                     }
                 },
                 Some(completed_request) = inflight_requests.join_next() => {
-                    match completed_request {
-                        Ok(()) => {
-                            trace!("request handler task completed");
-                        },
-                        Err(e) => {
-                            if e.is_cancelled() {
-                                trace!("request handler task was cancelled");
-                            } else if e.is_panic() {
-                                // If a task panics, just propagate it
-                                std::panic::resume_unwind(e.into_panic());
-                            } else {
-                                panic!("request handler task failed: {e}");
-                            }
-                        },
-                    };
+                    handle_completed_request(completed_request);
                 },
             }
         };
 
+        let (disconnect_reason, disconnect_cause) = match close_reason {
+            Some(e) => (
+                crate::types::DisconnectReason::from_quinn_error(&e),
+                crate::types::DisconnectCause::from_quinn_error(&e),
+            ),
+            None => {
+                debug!(peer =% self.connection.peer_id(), "draining in-flight requests");
+
+                // Give all in-flight requests the opportunity to complete before closing the
+                // connection
+                while let Some(completed_request) = inflight_requests.join_next().await {
+                    handle_completed_request(completed_request);
+                }
+
+                (
+                    crate::types::DisconnectReason::LocallyClosed,
+                    crate::types::DisconnectCause::default(),
+                )
+            }
+        };
+
         self.active_peers.remove_with_stable_id(
             self.connection.peer_id(),
             self.connection.stable_id(),
-            crate::types::DisconnectReason::from_quinn_error(&close_reason),
+            disconnect_reason,
+            disconnect_cause,
         );
 
         inflight_requests.shutdown().await;
 
         debug!(peer =% self.connection.peer_id(), "InboundRequestHandler ended");
     }
+
+    /// Spawn a task to handle a request once a permit from `request_limiter` has been acquired,
+    /// bounding the number of requests from this peer which are processed concurrently, followed
+    /// by a permit from the network-wide `request_scheduler`, if one is configured. The request's
+    /// stream is counted as closed once the task completes.
+    ///
+    /// The task is instrumented with the provided `span`, which should be a child of this
+    /// connection's span.
+    fn spawn_request<F>(
+        &self,
+        inflight_requests: &mut JoinSet<()>,
+        request_limiter: &Arc<Semaphore>,
+        handle: F,
+        span: Span,
+    ) where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let request_limiter = request_limiter.clone();
+        let request_scheduler = self.request_scheduler.clone();
+        let connection = self.connection.clone();
+
+        let queued_guard = connection.queue_inbound_request();
+        let stream_guard = connection.track_stream();
+
+        inflight_requests.spawn(
+            async move {
+                let _permit = request_limiter
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed");
+                let _scheduler_permit = match &request_scheduler {
+                    Some(request_scheduler) => {
+                        Some(request_scheduler.acquire(connection.stable_id()).await)
+                    }
+                    None => None,
+                };
+                drop(queued_guard);
+                let _guard = connection.start_inbound_request();
+
+                handle.await;
+                drop(stream_guard);
+            }
+            .instrument(span),
+        );
+    }
+}
+
+fn handle_completed_request(completed_request: Result<(), tokio::task::JoinError>) {
+    match completed_request {
+        Ok(()) => {
+            trace!("request handler task completed");
+        }
+        Err(e) => {
+            if e.is_cancelled() {
+                trace!("request handler task was cancelled");
+            } else if e.is_panic() {
+                // If a task panics, just propagate it
+                std::panic::resume_unwind(e.into_panic());
+            } else {
+                panic!("request handler task failed: {e}");
+            }
+        }
+    }
 }
 
 /// Handles a single incoming request from a peer. It receives the request, forwards it
@@ -121,21 +286,35 @@ impl InboundRequestHandler {
 struct BiStreamRequestHandler {
     connection: Connection,
     service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+    stream_handler: Option<StreamHandler>,
+    raw_stream_subscribers: RawStreamSubscribers,
+    metrics: Metrics,
+    compression: Option<CompressionConfig>,
+    idle_timeout: Duration,
     send_stream: FramedWrite<SendStream, LengthDelimitedCodec>,
     recv_stream: FramedRead<RecvStream, LengthDelimitedCodec>,
 }
 
 impl BiStreamRequestHandler {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         config: &Config,
         connection: Connection,
         service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+        stream_handler: Option<StreamHandler>,
+        raw_stream_subscribers: RawStreamSubscribers,
+        metrics: Metrics,
         send_stream: SendStream,
         recv_stream: RecvStream,
     ) -> Self {
         Self {
             connection,
             service,
+            stream_handler,
+            raw_stream_subscribers,
+            metrics,
+            compression: config.compression().cloned(),
+            idle_timeout: config.stream_idle_timeout(),
             send_stream: FramedWrite::new(send_stream, network_message_frame_codec(config)),
             recv_stream: FramedRead::new(recv_stream, network_message_frame_codec(config)),
         }
@@ -152,25 +331,56 @@ impl BiStreamRequestHandler {
         // Read Request
         //
 
-        let mut request = read_request(&mut self.recv_stream).await?;
+        let mut request = read_request(&mut self.recv_stream, self.idle_timeout).await?;
+        insert_connection_metadata(&self.connection, &mut request);
+        self.metrics.bytes_received(request.body().len());
+        let start = Instant::now();
 
-        // TODO maybe provide all of this via a single ConnectionMetadata type
-        //
-        // Provide Connection Metadata to the handler via extensions including:
-        // * PeerId
-        // * ConnectionOrigin
-        // * Remote SocketAddr
-        // * Direction of the Request
-        request.extensions_mut().insert(self.connection.peer_id());
-        request.extensions_mut().insert(self.connection.origin());
-        request
-            .extensions_mut()
-            .insert(self.connection.remote_address());
-        request.extensions_mut().insert(crate::Direction::Inbound);
+        // Raw streams are handed off, along with both halves of the stream, to a raw stream
+        // subscriber without going through the configured service
+        if request.route() == RAW_STREAM_ROUTE {
+            self.raw_stream_subscribers.dispatch((
+                self.connection.peer_id(),
+                RawSendStream::new(self.send_stream.into_inner()),
+                RawRecvStream::from_framed(self.recv_stream),
+            ));
+            return Ok(());
+        }
+
+        // Pings are responded to immediately without involving the configured service
+        if request.route() == PING_ROUTE {
+            write_response(&mut self.send_stream, Response::new(Bytes::new()), None).await?;
+            self.send_stream.get_mut().finish().await?;
+            return Ok(());
+        }
+
+        // Streaming RPCs are handed off, along with both halves of the stream, to the configured
+        // stream handler
+        if request
+            .headers()
+            .contains_key(crate::types::header::STREAMING)
+        {
+            let stream_handler = self
+                .stream_handler
+                .ok_or_else(|| anyhow::anyhow!("no stream handler configured"))?;
+            stream_handler(
+                request,
+                StreamSender::new(self.send_stream),
+                StreamReceiver::new(self.recv_stream),
+            )
+            .await;
+            return Ok(());
+        }
 
         // Issue request to configured Service
         // We also watch the send_stream and see if it has been prematurely terminated by the
-        // remote side indicating that this RPC was canceled.
+        // remote side indicating that this RPC was canceled, either explicitly or by the
+        // connection being lost. In that case the handler is dropped and the request's
+        // CancellationToken is cancelled, so that any work the handler spawned can stop as well.
+        let route = request.route().to_owned();
+        let cancellation_token = CancellationToken::new();
+        request.extensions_mut().insert(cancellation_token.clone());
+        let cancel_on_drop = cancellation_token.drop_guard();
         let response = {
             let handler = self.service.oneshot(request);
             let stopped = self.send_stream.get_mut().stopped();
@@ -179,14 +389,139 @@ impl BiStreamRequestHandler {
                 _ = stopped => return Err(anyhow::anyhow!("send_stream closed by remote")),
             }
         };
+        cancel_on_drop.disarm();
 
         //
         // Write Response
         //
 
-        write_response(&mut self.send_stream, response).await?;
+        self.metrics.rpc_completed(
+            self.connection.peer_id(),
+            crate::Direction::Inbound,
+            &route,
+            RpcOutcome::from_response(&response),
+            start.elapsed(),
+        );
+
+        // The handler asked for the stream to be reset with an application error code instead of
+        // sending the response
+        if let Some(ApplicationErrorCode(code)) =
+            response.extensions().get::<ApplicationErrorCode>().copied()
+        {
+            self.send_stream
+                .get_mut()
+                .reset(quinn::VarInt::from_u64(code)?)?;
+            return Ok(());
+        }
+
+        self.metrics.bytes_sent(response.body().len());
+        write_response(&mut self.send_stream, response, self.compression.as_ref()).await?;
         self.send_stream.get_mut().finish().await?;
+        self.connection.record_activity();
 
         Ok(())
     }
 }
+
+/// Handles a single incoming message from a peer. It receives the message and forwards it to the
+/// service for processing, discarding the response as the peer isn't waiting for one.
+///
+/// Streams opened by an [`OrderedSender`](crate::OrderedSender) instead carry a sequence of
+/// messages, which are forwarded to the service one at a time until the stream is finished.
+struct UniStreamRequestHandler {
+    connection: Connection,
+    service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+    push_subscribers: PushSubscribers,
+    metrics: Metrics,
+    idle_timeout: Duration,
+    frame_codec: LengthDelimitedCodec,
+    recv_stream: FramedRead<RecvStream, LengthDelimitedCodec>,
+}
+
+impl UniStreamRequestHandler {
+    fn new(
+        config: &Config,
+        connection: Connection,
+        service: BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible>,
+        push_subscribers: PushSubscribers,
+        metrics: Metrics,
+        recv_stream: RecvStream,
+    ) -> Self {
+        Self {
+            connection,
+            service,
+            push_subscribers,
+            metrics,
+            idle_timeout: config.stream_idle_timeout(),
+            frame_codec: network_message_frame_codec(config),
+            recv_stream: FramedRead::new(recv_stream, network_message_frame_codec(config)),
+        }
+    }
+
+    async fn handle(self) {
+        if let Err(e) = self.do_handle().await {
+            trace!("handling message failed: {e}");
+        }
+    }
+
+    async fn do_handle(mut self) -> Result<()> {
+        let request = read_request(&mut self.recv_stream, self.idle_timeout).await?;
+
+        if request.route() == PUSH_ROUTE {
+            self.metrics.bytes_received(request.body().len());
+            self.push_subscribers
+                .dispatch(self.connection.peer_id(), request.into_body())
+                .await;
+            return Ok(());
+        }
+
+        if request.route() != ORDERED_MESSAGES_ROUTE {
+            self.handle_message(request).await;
+            return Ok(());
+        }
+
+        // The sender can go quiet for arbitrarily long between messages, so only the reading of
+        // each individual message is subject to the idle timeout
+        while let Some(frame) = self.recv_stream.next().await {
+            let request =
+                decode_request(&frame?, self.frame_codec.clone(), self.idle_timeout).await?;
+            self.handle_message(request).await;
+        }
+
+        Ok(())
+    }
+
+    // The service is cloned up front so that the returned future doesn't borrow `self`, as the
+    // service is not `Sync`.
+    fn handle_message(&self, mut request: Request<Bytes>) -> impl Future<Output = ()> + Send {
+        insert_connection_metadata(&self.connection, &mut request);
+        self.metrics.bytes_received(request.body().len());
+
+        let service = self.service.clone();
+        async move {
+            let _response = service.oneshot(request).await.expect("Infallible");
+        }
+    }
+}
+
+/// Provide Connection Metadata to the handler via extensions including:
+/// * PeerId
+/// * ConnectionOrigin
+/// * Remote SocketAddr
+/// * Direction of the Request
+/// * Deadline of the Request, if the sender provided a timeout
+fn insert_connection_metadata(connection: &Connection, request: &mut Request<Bytes>) {
+    // TODO maybe provide all of this via a single ConnectionMetadata type
+    request.extensions_mut().insert(connection.peer_id());
+    request.extensions_mut().insert(connection.origin());
+    request.extensions_mut().insert(connection.remote_address());
+    request.extensions_mut().insert(crate::Direction::Inbound);
+    if let Some(deadline) = request
+        .timeout()
+        .and_then(|timeout| std::time::Instant::now().checked_add(timeout))
+    {
+        request
+            .extensions_mut()
+            .insert(crate::types::request::Deadline(deadline));
+    }
+}
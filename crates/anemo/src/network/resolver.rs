@@ -0,0 +1,23 @@
+use crate::{PeerId, Result};
+use async_trait::async_trait;
+use std::net::SocketAddr;
+
+/// Resolves the addresses at which a peer can be reached.
+///
+/// When a [`PeerAffinity::High`](crate::types::PeerAffinity::High) known peer has no addresses,
+/// e.g. because it was learned about from another peer, the network asks its resolver, if one is
+/// configured via [`Builder::peer_resolver`](crate::Builder::peer_resolver), where to dial it.
+/// This allows integrating DNS or a discovery service without the network having to know about
+/// it. Without a resolver only the addresses stored in [`KnownPeers`](crate::KnownPeers) are
+/// dialed.
+#[async_trait]
+pub trait PeerResolver: Send + Sync + 'static {
+    /// Returns candidate addresses for `peer_id`.
+    ///
+    /// Successive dial attempts cycle through the returned addresses. The identity of the peer
+    /// reached at an address is always verified, so a resolver can't cause a connection to be
+    /// established with a different peer. Resolution is subject to the same
+    /// [`connect_timeout_ms`](crate::Config::connect_timeout_ms) as dialing, after which the
+    /// attempt counts as failed and is retried after backing off.
+    async fn resolve(&self, peer_id: PeerId) -> Result<Vec<SocketAddr>>;
+}
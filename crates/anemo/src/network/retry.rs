@@ -0,0 +1,74 @@
+use crate::RpcError;
+use std::time::Duration;
+
+/// Controls how [`Peer::rpc_with_retry`](crate::Peer::rpc_with_retry) retries RPCs which fail due
+/// to transient transport errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts to make, including the first.
+    ///
+    /// If unspecified, this will default to `3`.
+    pub max_attempts: u32,
+
+    /// How long to wait before the first retry. The delay doubles after each subsequent retry,
+    /// up to `max_backoff`.
+    ///
+    /// If unspecified, this will default to `100` milliseconds.
+    pub initial_backoff: Duration,
+
+    /// Maximum delay between two attempts.
+    ///
+    /// If unspecified, this will default to `2` seconds.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns whether an RPC which failed with `error` may be retried.
+    ///
+    /// Only failures of the transport are retried. A response with an unsuccessful status is
+    /// returned as-is, as the peer has already processed the request.
+    pub(crate) fn is_retryable(error: &RpcError) -> bool {
+        matches!(
+            error,
+            RpcError::ConnectionLost | RpcError::Timeout | RpcError::RemoteClosed
+        )
+    }
+
+    /// Returns how long to wait before making attempt number `attempt + 1`.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RetryPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(64), Duration::from_millis(500));
+    }
+}
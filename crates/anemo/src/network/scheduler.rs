@@ -0,0 +1,201 @@
+use crate::config::RequestScheduling;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+
+/// Bounds the number of inbound requests processed concurrently across all connections.
+///
+/// When no capacity is available, requests wait for a [`RequestPermit`] to be released. Waiting
+/// requests are granted permits either in the order they arrived ([`RequestScheduling::Fifo`]), or
+/// round-robin across the connections they arrived on ([`RequestScheduling::Fair`]) so that a peer
+/// with many queued requests can't starve a peer with only a few.
+#[derive(Clone)]
+pub(crate) struct RequestScheduler {
+    inner: Arc<Mutex<SchedulerState>>,
+}
+
+struct SchedulerState {
+    scheduling: RequestScheduling,
+    /// Number of permits which can be granted immediately. This is only ever nonzero when there
+    /// are no waiting requests.
+    available: usize,
+    /// Requests waiting for a permit, keyed by lane.
+    waiters: HashMap<usize, VecDeque<oneshot::Sender<RequestPermit>>>,
+    /// Lanes with waiting requests, in the order they'll be served.
+    lanes: VecDeque<usize>,
+}
+
+impl SchedulerState {
+    fn lane(&self, stable_id: usize) -> usize {
+        match self.scheduling {
+            // All requests share a single lane, so permits are granted in arrival order
+            RequestScheduling::Fifo => 0,
+            RequestScheduling::Fair => stable_id,
+        }
+    }
+
+    fn next_waiter(&mut self) -> Option<oneshot::Sender<RequestPermit>> {
+        let lane = self.lanes.pop_front()?;
+        let queue = self
+            .waiters
+            .get_mut(&lane)
+            .expect("lanes only contains lanes with waiters");
+        let waiter = queue.pop_front().expect("lanes are never empty");
+
+        if queue.is_empty() {
+            self.waiters.remove(&lane);
+        } else {
+            // Move to the back of the line so that other lanes get a turn
+            self.lanes.push_back(lane);
+        }
+
+        Some(waiter)
+    }
+}
+
+impl RequestScheduler {
+    pub fn new(max_concurrent_requests: usize, scheduling: RequestScheduling) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SchedulerState {
+                scheduling,
+                available: max_concurrent_requests,
+                waiters: HashMap::new(),
+                lanes: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Wait for a permit to process a request which arrived on the connection with the provided
+    /// `stable_id`.
+    pub async fn acquire(&self, stable_id: usize) -> RequestPermit {
+        let receiver = {
+            let mut state = self.inner.lock().unwrap();
+
+            if state.available > 0 {
+                state.available -= 1;
+                return RequestPermit {
+                    inner: self.inner.clone(),
+                };
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let lane = state.lane(stable_id);
+            let is_new_lane = match state.waiters.get(&lane) {
+                Some(queue) => queue.is_empty(),
+                None => true,
+            };
+            if is_new_lane {
+                state.lanes.push_back(lane);
+            }
+            state.waiters.entry(lane).or_default().push_back(sender);
+
+            receiver
+        };
+
+        // If this future is dropped after a permit has been sent, the permit is dropped along with
+        // the receiver and handed on to the next waiter
+        receiver
+            .await
+            .expect("sender is only dropped after sending a permit")
+    }
+}
+
+/// Permission to process a single inbound request, released back to the [`RequestScheduler`] on
+/// drop.
+pub(crate) struct RequestPermit {
+    inner: Arc<Mutex<SchedulerState>>,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        loop {
+            let waiter = {
+                let mut state = self.inner.lock().unwrap();
+                match state.next_waiter() {
+                    Some(waiter) => waiter,
+                    None => {
+                        state.available += 1;
+                        return;
+                    }
+                }
+            };
+
+            let permit = RequestPermit {
+                inner: self.inner.clone(),
+            };
+            match waiter.send(permit) {
+                Ok(()) => return,
+                // The waiting request was cancelled, so hand the permit to the next one instead.
+                // The returned permit is forgotten so that it isn't released a second time.
+                Err(permit) => std::mem::forget(permit),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RequestScheduler;
+    use crate::config::RequestScheduling;
+    use futures::FutureExt;
+
+    async fn grant_order(scheduling: RequestScheduling) -> Vec<usize> {
+        let scheduler = RequestScheduler::new(1, scheduling);
+        let mut permit = scheduler.acquire(0).await;
+
+        // Connection 1 queues three requests before connection 2 queues one
+        let mut waiting = Vec::new();
+        for stable_id in [1, 1, 1, 2] {
+            let mut acquire = Box::pin(scheduler.acquire(stable_id));
+            assert!((&mut acquire).now_or_never().is_none());
+            waiting.push((stable_id, acquire));
+        }
+
+        let mut order = Vec::new();
+        while !waiting.is_empty() {
+            drop(permit);
+            let (idx, next) = waiting
+                .iter_mut()
+                .enumerate()
+                .find_map(|(idx, (_, acquire))| {
+                    acquire.as_mut().now_or_never().map(|permit| (idx, permit))
+                })
+                .expect("releasing a permit grants it to a waiter");
+            order.push(waiting.remove(idx).0);
+            permit = next;
+        }
+
+        order
+    }
+
+    #[tokio::test]
+    async fn fifo() {
+        assert_eq!(grant_order(RequestScheduling::Fifo).await, [1, 1, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn fair() {
+        assert_eq!(grant_order(RequestScheduling::Fair).await, [1, 2, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiters_are_skipped() {
+        let scheduler = RequestScheduler::new(1, RequestScheduling::Fair);
+        let permit = scheduler.acquire(0).await;
+
+        let mut cancelled = Box::pin(scheduler.acquire(1));
+        assert!((&mut cancelled).now_or_never().is_none());
+        let mut waiting = Box::pin(scheduler.acquire(2));
+        assert!((&mut waiting).now_or_never().is_none());
+        drop(cancelled);
+
+        drop(permit);
+        let permit = waiting.now_or_never().unwrap();
+
+        // Once released, the permit is available to new requests
+        drop(permit);
+        assert!(scheduler.acquire(3).now_or_never().is_some());
+    }
+}
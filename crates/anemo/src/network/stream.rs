@@ -0,0 +1,127 @@
+use crate::{connection::SendStream, types::header, Request, Result};
+use bytes::{Bytes, BytesMut};
+use futures::{
+    future::BoxFuture,
+    stream::{BoxStream, Stream},
+    FutureExt, SinkExt, StreamExt,
+};
+use quinn::RecvStream;
+use std::sync::Arc;
+use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+
+/// Type-erased handler for inbound streaming RPCs.
+pub(crate) type StreamHandler = Arc<
+    dyn Fn(Request<Bytes>, StreamSender, StreamReceiver) -> BoxFuture<'static, ()> + Send + Sync,
+>;
+
+/// Type-erased handler for inbound server-streaming RPCs.
+pub(crate) type ServerStreamHandler =
+    Arc<dyn Fn(Request<Bytes>) -> BoxStream<'static, Bytes> + Send + Sync>;
+
+/// Combine the configured handlers into a single [`StreamHandler`], which dispatches
+/// server-streaming RPCs to `server_stream_handler` and all other streaming RPCs to
+/// `stream_handler`.
+///
+/// Streaming RPCs without a matching handler are rejected by resetting the stream.
+pub(crate) fn combine_stream_handlers(
+    stream_handler: Option<StreamHandler>,
+    server_stream_handler: Option<ServerStreamHandler>,
+) -> Option<StreamHandler> {
+    let server_stream_handler = match server_stream_handler {
+        Some(server_stream_handler) => server_stream_handler,
+        None => return stream_handler,
+    };
+
+    let handler: StreamHandler = Arc::new(
+        move |request: Request<Bytes>, sender: StreamSender, receiver: StreamReceiver| {
+            if request.headers().contains_key(header::SERVER_STREAMING) {
+                serve_stream(server_stream_handler(request), sender).boxed()
+            } else if let Some(stream_handler) = &stream_handler {
+                stream_handler(request, sender, receiver)
+            } else {
+                futures::future::ready(()).boxed()
+            }
+        },
+    );
+    Some(handler)
+}
+
+/// Write each chunk produced by `stream` as its own frame, finishing the stream once all chunks
+/// have been sent.
+async fn serve_stream(mut stream: BoxStream<'static, Bytes>, mut sender: StreamSender) {
+    while let Some(chunk) = stream.next().await {
+        // The peer is no longer reading the stream
+        if sender.send(chunk).await.is_err() {
+            return;
+        }
+    }
+
+    let _ = sender.finish().await;
+}
+
+/// Turn the receiving half of a server-streaming RPC into a [`Stream`] of chunks.
+///
+/// The stream ends after the first error, e.g. due to the server resetting the stream.
+pub(crate) fn into_chunk_stream(receiver: StreamReceiver) -> impl Stream<Item = Result<Bytes>> {
+    futures::stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        match receiver.recv().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(receiver))),
+            Ok(None) => None,
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+/// The sending half of a streaming RPC.
+///
+/// Each call to [`StreamSender::send`] writes a single length-delimited frame to the underlying
+/// QUIC stream. Dropping a `StreamSender` without calling [`StreamSender::finish`] resets the
+/// stream, signaling to the remote side that the stream was aborted.
+pub struct StreamSender {
+    send_stream: FramedWrite<SendStream, LengthDelimitedCodec>,
+}
+
+impl StreamSender {
+    pub(crate) fn new(send_stream: FramedWrite<SendStream, LengthDelimitedCodec>) -> Self {
+        Self { send_stream }
+    }
+
+    /// Send a single frame to the remote side.
+    pub async fn send(&mut self, frame: Bytes) -> Result<()> {
+        self.send_stream.send(frame).await.map_err(Into::into)
+    }
+
+    /// Gracefully close this half of the stream.
+    ///
+    /// Once the remote side has read all outstanding frames its [`StreamReceiver`] will yield
+    /// `None`. The other half of the stream is unaffected and can continue to be read from.
+    pub async fn finish(mut self) -> Result<()> {
+        SinkExt::<Bytes>::flush(&mut self.send_stream).await?;
+        self.send_stream.get_mut().finish().await?;
+        Ok(())
+    }
+}
+
+/// The receiving half of a streaming RPC.
+pub struct StreamReceiver {
+    recv_stream: FramedRead<RecvStream, LengthDelimitedCodec>,
+}
+
+impl StreamReceiver {
+    pub(crate) fn new(recv_stream: FramedRead<RecvStream, LengthDelimitedCodec>) -> Self {
+        Self { recv_stream }
+    }
+
+    /// Receive the next frame from the remote side.
+    ///
+    /// Returns `Ok(None)` once the remote side has finished its half of the stream.
+    pub async fn recv(&mut self) -> Result<Option<Bytes>> {
+        self.recv_stream
+            .next()
+            .await
+            .transpose()
+            .map(|frame| frame.map(BytesMut::freeze))
+            .map_err(Into::into)
+    }
+}
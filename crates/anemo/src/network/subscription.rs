@@ -0,0 +1,73 @@
+use super::connection_manager::{ActivePeers, ActivePeersRef};
+use crate::{types::PeerEvent, PeerId};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// An item received from a [`Subscription`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubscriptionEvent {
+    /// The next peer event.
+    Event(PeerEvent),
+
+    /// The subscription fell behind and missed events. This is the complete set of connected
+    /// peers, which the subscriber should reconcile its view with. Events received after this one
+    /// apply on top of it.
+    Resync(Vec<PeerId>),
+}
+
+/// A subscription to [`PeerEvent`]s which recovers from missed events.
+///
+/// Unlike the raw receiver returned by [`Network::subscribe`](crate::Network::subscribe), which
+/// only reports how many events were skipped when a subscriber falls behind, a `Subscription`
+/// hands the subscriber a fresh snapshot of the connected peers so that a missed
+/// [`PeerEvent::LostPeer`] can't leave it with a stale view.
+///
+/// Created via [`Network::subscription`](crate::Network::subscription).
+pub struct Subscription {
+    receiver: broadcast::Receiver<PeerEvent>,
+    active_peers: ActivePeersRef,
+}
+
+impl Subscription {
+    pub(crate) fn new(
+        receiver: broadcast::Receiver<PeerEvent>,
+        active_peers: ActivePeersRef,
+    ) -> Self {
+        Self {
+            receiver,
+            active_peers,
+        }
+    }
+
+    /// Receive the next event, or a [`SubscriptionEvent::Resync`] if events were missed.
+    ///
+    /// Returns `None` once the network has been shutdown.
+    pub async fn recv(&mut self) -> Option<SubscriptionEvent> {
+        match self.receiver.recv().await {
+            Ok(event) => Some(SubscriptionEvent::Event(event)),
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::debug!("peer event subscriber lagged by {skipped} events, resyncing");
+                Some(SubscriptionEvent::Resync(self.resync()))
+            }
+            Err(RecvError::Closed) => None,
+        }
+    }
+
+    /// Discard any pending events and return the set of currently connected peers.
+    ///
+    /// The snapshot is taken atomically with resubscribing, so subsequently received events
+    /// apply exactly on top of it. Returns an empty set if the network has been shutdown.
+    pub fn resync(&mut self) -> Vec<PeerId> {
+        match self
+            .active_peers
+            .upgrade()
+            .as_ref()
+            .map(ActivePeers::subscribe)
+        {
+            Some((receiver, peers)) => {
+                self.receiver = receiver;
+                peers
+            }
+            None => Vec::new(),
+        }
+    }
+}
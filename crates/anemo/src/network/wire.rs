@@ -1,31 +1,36 @@
 // Wire format
 
 use crate::{
+    config::CompressionConfig,
+    connection::{close_code, SendStream},
     types::{
+        header,
         request::{RawRequestHeader, RequestHeader},
         response::{RawResponseHeader, ResponseHeader},
-        Version,
+        HeaderMap, Version,
     },
-    Config, Request, Response, Result,
+    Config, ConnectError, Request, Response, Result,
 };
 use anyhow::{anyhow, bail};
 use bytes::{BufMut, Bytes, BytesMut};
 use futures::{SinkExt, StreamExt};
+use std::{io, time::Duration};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
 
 const ANEMO: &[u8; 5] = b"anemo";
+const ZSTD: &str = "zstd";
+/// Length, in bytes, of the big-endian length prefix of each frame.
+const FRAME_LENGTH_FIELD_LENGTH: usize = 4;
 
 /// Returns a fully configured length-delimited codec for writing/reading
 /// serialized frames to/from a socket.
 pub(crate) fn network_message_frame_codec(config: &Config) -> LengthDelimitedCodec {
-    let mut builder = LengthDelimitedCodec::builder();
-
-    if let Some(max_frame_size) = config.max_frame_size() {
-        builder.max_frame_length(max_frame_size);
-    }
-
-    builder.length_field_length(4).big_endian().new_codec()
+    LengthDelimitedCodec::builder()
+        .max_frame_length(config.max_frame_size())
+        .length_field_length(FRAME_LENGTH_FIELD_LENGTH)
+        .big_endian()
+        .new_codec()
 }
 
 /// Anemo requires mTLS in order to ensure that both sides of the connections are authenticated by
@@ -38,21 +43,247 @@ pub(crate) fn network_message_frame_codec(config: &Config) -> LengthDelimitedCod
 ///
 /// Performing this small handshake will also enable the server side to make decisions about
 /// whether to keep the connection based on things like the client side's PeerId.
+///
+/// The server always starts by opening a uni-directional stream and writing a [`Version::V1`]
+/// frame, which is all that peers predating version negotiation send or expect. Newer servers
+/// follow it with a [`Hello`] advertising the highest wire protocol version they support, their
+/// capabilities and their network id, and leave the stream open. A dialer which understands the
+/// hello answers with its own on a stream of its own, and the server acknowledges the connection
+/// by finishing its stream once it has decided to keep it. Older dialers stop reading after the
+/// version frame, which is how the server knows not to wait for a hello from them, while older
+/// servers finish their stream right after the version frame, which is how the dialer knows not
+/// to send one.
+///
+/// Note that older dialers take the version frame itself as the server's acknowledgement. The
+/// server can't tell an older dialer apart from a newer one until it has sent the frame, which
+/// happens before it has decided whether to keep the connection. As a result an older dialer
+/// dialing a server which goes on to refuse the connection, e.g. due to connection limits, sees
+/// the connection established and then promptly closed, rather than failing to connect.
+///
+/// This performs the dialer's side of the handshake, see [`accept_handshake`] for the server's.
 pub(crate) async fn handshake(
     connection: crate::connection::Connection,
+    config: &Config,
+    capabilities: &[String],
 ) -> Result<crate::connection::Connection> {
-    match connection.origin() {
-        crate::ConnectionOrigin::Inbound => {
-            let mut send_stream = connection.open_uni().await?;
+    #[cfg(test)]
+    if config.legacy_handshake {
+        let mut recv_stream = connection.accept_uni().await?;
+        read_version_frame(&mut recv_stream).await?;
+        return Ok(connection);
+    }
+
+    let mut recv_stream = connection.accept_uni().await?;
+    read_version_frame(&mut recv_stream).await?;
+
+    let remote_hello = match read_hello(&mut recv_stream, config.max_frame_size()).await? {
+        Some(remote_hello) => remote_hello,
+        // The server predates version negotiation and has already accepted the connection
+        None => return Hello::legacy().apply(connection, config),
+    };
+    let connection = remote_hello.apply(connection, config)?;
+
+    let mut send_stream = connection.open_uni().await?;
+    write_hello(&mut send_stream, &Hello::new(config, capabilities)).await?;
+    send_stream.finish().await?;
+
+    // Wait for the server to acknowledge the connection by finishing its stream
+    recv_stream.read_to_end(0).await?;
+
+    Ok(connection)
+}
+
+/// Perform the server's side of the handshake with the dialer of an inbound connection, see
+/// [`handshake`].
+///
+/// The returned [`PendingHandshake`] must be [accepted](PendingHandshake::accept) for the dialer
+/// to consider the connection established, unless the dialer predates version negotiation, in
+/// which case it already has.
+pub(crate) async fn accept_handshake(
+    connection: crate::connection::Connection,
+    config: &Config,
+    capabilities: &[String],
+) -> Result<PendingHandshake> {
+    #[cfg(test)]
+    if config.legacy_handshake {
+        return Ok(PendingHandshake {
+            connection,
+            send_stream: None,
+            legacy: true,
+        });
+    }
+
+    let mut send_stream = connection.open_uni().await?;
+    let mut buf = Vec::new();
+    write_version_frame(&mut buf, Version::V1).await?;
+    write_hello(&mut buf, &Hello::new(config, capabilities)).await?;
+    send_stream.write_all(&buf).await?;
+
+    let remote_hello = tokio::select! {
+        recv_stream = connection.accept_uni() => {
+            read_hello(&mut recv_stream?, config.max_frame_size())
+                .await?
+                .ok_or_else(|| anyhow!("peer {} sent an empty hello", connection.peer_id()))?
+        }
+        // The dialer predates version negotiation and stopped reading after the version frame
+        stopped = send_stream.stopped() => {
+            if let Err(e) = stopped {
+                return Err(match e {
+                    quinn::StoppedError::ConnectionLost(e) => e.into(),
+                    e => e.into(),
+                });
+            }
+            return Ok(PendingHandshake {
+                connection: Hello::legacy().apply(connection, config)?,
+                send_stream: None,
+                legacy: false,
+            });
+        }
+    };
+
+    Ok(PendingHandshake {
+        connection: remote_hello.apply(connection, config)?,
+        send_stream: Some(send_stream),
+        legacy: false,
+    })
+}
+
+/// An inbound connection which has completed the handshake but is yet to be acknowledged to the
+/// dialer.
+pub(crate) struct PendingHandshake {
+    connection: crate::connection::Connection,
+    // Stream the hello was sent on, which is finished to acknowledge the connection
+    send_stream: Option<SendStream>,
+    // Whether we are simulating a peer which predates version negotiation
+    legacy: bool,
+}
+
+impl PendingHandshake {
+    pub fn connection(&self) -> &crate::connection::Connection {
+        &self.connection
+    }
+
+    /// Acknowledge the connection, letting the dialer know that it has been established.
+    pub async fn accept(self) -> Result<crate::connection::Connection> {
+        if self.legacy {
+            let mut send_stream = self.connection.open_uni().await?;
             write_version_frame(&mut send_stream, Version::V1).await?;
             send_stream.finish().await?;
+        } else if let Some(mut send_stream) = self.send_stream {
+            send_stream.finish().await?;
+        }
+
+        Ok(self.connection)
+    }
+}
+
+/// What each side advertises about itself during the handshake.
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct Hello {
+    /// The highest wire protocol version supported.
+    version: u16,
+    /// The routes served, see [`Builder::capabilities`](crate::Builder::capabilities).
+    capabilities: Vec<String>,
+    /// See [`Config::network_id`].
+    network_id: Option<[u8; 32]>,
+}
+
+impl Hello {
+    fn new(config: &Config, capabilities: &[String]) -> Self {
+        Self {
+            version: config.protocol_version(),
+            capabilities: capabilities.to_vec(),
+            network_id: config.network_id().copied(),
         }
-        crate::ConnectionOrigin::Outbound => {
-            let mut recv_stream = connection.accept_uni().await?;
-            read_version_frame(&mut recv_stream).await?;
+    }
+
+    /// What a peer which predates version negotiation is treated as having advertised.
+    fn legacy() -> Self {
+        Self {
+            version: Version::V1.to_u16(),
+            ..Default::default()
         }
     }
-    Ok(connection)
+
+    /// Settle on a version with the peer which sent this hello and check that it belongs to our
+    /// network, closing the connection if not.
+    fn apply(
+        self,
+        connection: crate::connection::Connection,
+        config: &Config,
+    ) -> Result<crate::connection::Connection> {
+        let local_version = config.protocol_version();
+        let version = match negotiate_version(local_version, self.version) {
+            Some(version) => version,
+            None => {
+                connection
+                    .close_with_reason(close_code::VERSION_MISMATCH, b"no common protocol version");
+                return Err(
+                    anyhow::Error::new(ConnectError::VersionMismatch).context(format!(
+                        "no common protocol version with peer {}: local version {local_version}, \
+                         remote version {}",
+                        connection.peer_id(),
+                        self.version,
+                    )),
+                );
+            }
+        };
+
+        if let Some(network_id) = config.network_id() {
+            if self.network_id.as_ref() != Some(network_id) {
+                connection.close_with_reason(close_code::NETWORK_MISMATCH, b"network id mismatch");
+                return Err(ConnectError::Refused(format!(
+                    "peer {} belongs to a different network: local network id {}, remote \
+                     network id {}",
+                    connection.peer_id(),
+                    hex::encode(network_id),
+                    self.network_id
+                        .map(hex::encode)
+                        .unwrap_or_else(|| "none".to_owned()),
+                ))
+                .into());
+            }
+        }
+
+        Ok(connection
+            .with_version(version)
+            .with_capabilities(self.capabilities.into_iter().collect()))
+    }
+}
+
+async fn write_hello<T: AsyncWrite + Unpin>(send_stream: &mut T, hello: &Hello) -> Result<()> {
+    let buf = bincode::serialize(hello).expect("serialization should not fail");
+    send_stream.write_u32(buf.len() as u32).await?;
+    send_stream.write_all(&buf).await?;
+
+    Ok(())
+}
+
+/// Read a length-prefixed [`Hello`] of at most `max_size` bytes, or `None` if the stream ends
+/// without one.
+async fn read_hello<T: AsyncRead + Unpin>(
+    recv_stream: &mut T,
+    max_size: usize,
+) -> Result<Option<Hello>> {
+    let mut len = [0; 4];
+    if recv_stream.read(&mut len[..1]).await? == 0 {
+        return Ok(None);
+    }
+    recv_stream.read_exact(&mut len[1..]).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_size {
+        bail!("hello of {len} bytes exceeds the maximum of {max_size} bytes");
+    }
+
+    let mut buf = vec![0; len];
+    recv_stream.read_exact(&mut buf).await?;
+    Ok(Some(bincode::deserialize(&buf)?))
+}
+
+/// Determine the wire protocol version to use with a peer, given the highest version supported by
+/// each side, or `None` if the versions are incompatible.
+fn negotiate_version(local_version: u16, remote_version: u16) -> Option<Version> {
+    Version::new(std::cmp::min(local_version, remote_version)).ok()
 }
 
 pub(crate) async fn read_version_frame<T: AsyncRead + Unpin>(
@@ -81,14 +312,66 @@ pub(crate) async fn write_version_frame<T: AsyncWrite + Unpin>(
     Ok(())
 }
 
+/// Compress `body` if compression is enabled and the body is large enough, recording the
+/// compression algorithm used in `headers` so that the receiver knows to decompress it.
+fn compress_body(
+    headers: &mut HeaderMap,
+    body: Bytes,
+    compression: Option<&CompressionConfig>,
+) -> Result<Bytes> {
+    let compression = match compression {
+        Some(compression) if body.len() >= compression.min_size() => compression,
+        _ => return Ok(body),
+    };
+
+    let compressed = zstd::bulk::compress(&body, compression.level())?;
+
+    // Only send the compressed body if it actually ended up being smaller
+    if compressed.len() < body.len() {
+        headers.insert(header::BODY_COMPRESSION.into(), ZSTD.into());
+        Ok(compressed.into())
+    } else {
+        Ok(body)
+    }
+}
+
+/// Decompress `body` if `headers` indicate that it was compressed by the sender.
+///
+/// The decompressed body is bounded by `max_size` in order to prevent a peer from exhausting our
+/// memory with a small, highly compressible body.
+fn decompress_body(headers: &mut HeaderMap, body: Bytes, max_size: usize) -> Result<Bytes> {
+    use std::io::Read;
+
+    match headers.remove(header::BODY_COMPRESSION).as_deref() {
+        None => Ok(body),
+        Some(ZSTD) => {
+            let mut decoder = zstd::stream::read::Decoder::with_buffer(body.as_ref())?;
+            let mut decompressed = Vec::new();
+            decoder
+                .by_ref()
+                .take(max_size as u64 + 1)
+                .read_to_end(&mut decompressed)?;
+
+            if decompressed.len() > max_size {
+                bail!("decompressed body exceeds the maximum frame size of {max_size} bytes");
+            }
+
+            Ok(decompressed.into())
+        }
+        Some(unknown) => bail!("unsupported body compression: {unknown}"),
+    }
+}
+
 pub(crate) async fn write_request<T: AsyncWrite + Unpin>(
     send_stream: &mut FramedWrite<T, LengthDelimitedCodec>,
     request: Request<Bytes>,
+    compression: Option<&CompressionConfig>,
 ) -> Result<()> {
     // Write Version Frame
     write_version_frame(send_stream.get_mut(), request.version()).await?;
 
-    let (parts, body) = request.into_parts();
+    let (mut parts, body) = request.into_parts();
+    let body = compress_body(&mut parts.headers, body, compression)?;
 
     // Write Request Header
     let raw_header = RawRequestHeader::from_header(parts);
@@ -103,16 +386,22 @@ pub(crate) async fn write_request<T: AsyncWrite + Unpin>(
     Ok(())
 }
 
-pub(crate) async fn write_response<T: AsyncWrite + Unpin>(
-    send_stream: &mut FramedWrite<T, LengthDelimitedCodec>,
+/// Write `response` to `send_stream`.
+///
+/// The body is written without being copied into the codec's buffer, so a body sliced from a
+/// larger shared buffer is sent straight from that buffer.
+pub(crate) async fn write_response(
+    send_stream: &mut FramedWrite<SendStream, LengthDelimitedCodec>,
     response: Response<Bytes>,
+    compression: Option<&CompressionConfig>,
 ) -> Result<()> {
     // Write Version Frame
     write_version_frame(send_stream.get_mut(), response.version()).await?;
 
     // We keep extensions alive so that any RAII objects contained therein
     // are not dropped until the response is sent.
-    let (parts, body) = response.into_parts();
+    let (mut parts, body) = response.into_parts();
+    let body = compress_body(&mut parts.headers, body, compression)?;
     let (raw_header, _extensions) = RawResponseHeader::from_header(parts);
 
     // Write Request Header
@@ -121,37 +410,138 @@ pub(crate) async fn write_response<T: AsyncWrite + Unpin>(
         .expect("serialization should not fail");
     send_stream.send(buf.freeze()).await?;
 
-    // Write Body
-    send_stream.send(body).await?;
+    // Write Body. Sending the header flushed the codec's buffer, so the body frame can be written
+    // to the underlying stream directly.
+    let mut chunks = frame_chunks(body, send_stream.encoder())?;
+    send_stream.get_mut().write_all_chunks(&mut chunks).await?;
 
     Ok(())
 }
 
+/// Split a length-delimited frame for `body` into its length prefix and the body itself, so that
+/// the body can be written without copying it.
+fn frame_chunks(body: Bytes, codec: &LengthDelimitedCodec) -> Result<[Bytes; 2]> {
+    if body.len() > codec.max_frame_length() {
+        bail!(
+            "frame of {} bytes exceeds the maximum frame size of {} bytes",
+            body.len(),
+            codec.max_frame_length()
+        );
+    }
+    if (body.len() as u64) >> (8 * FRAME_LENGTH_FIELD_LENGTH) != 0 {
+        bail!(
+            "frame of {} bytes doesn't fit in a {FRAME_LENGTH_FIELD_LENGTH} byte length field",
+            body.len()
+        );
+    }
+
+    // Written the same way as the length field of `network_message_frame_codec`
+    let mut len = BytesMut::with_capacity(FRAME_LENGTH_FIELD_LENGTH);
+    len.put_uint(body.len() as u64, FRAME_LENGTH_FIELD_LENGTH);
+    Ok([len.freeze(), body])
+}
+
+/// Read a request from `recv_stream`, failing if no data arrives for `idle_timeout` at any point
+/// while reading it.
 pub(crate) async fn read_request<T: AsyncRead + Unpin>(
     recv_stream: &mut FramedRead<T, LengthDelimitedCodec>,
+    idle_timeout: Duration,
 ) -> Result<Request<Bytes>> {
     // Read Version Frame
-    let version = read_version_frame(recv_stream.get_mut()).await?;
+    let version = tokio::time::timeout(idle_timeout, read_version_frame(recv_stream.get_mut()))
+        .await
+        .map_err(|_| anyhow!("no data received on stream for {idle_timeout:?}"))??;
 
     // Read Request Header
-    let header_buf = recv_stream
-        .next()
-        .await
-        .ok_or_else(|| anyhow!("unexpected EOF"))??;
+    let header_buf = next_frame_with_idle_timeout(recv_stream, idle_timeout).await?;
     let raw_header: RawRequestHeader = bincode::deserialize(&header_buf)?;
-    let request_header = RequestHeader::from_raw(raw_header, version);
+    let mut request_header = RequestHeader::from_raw(raw_header, version);
 
     // Read Body
-    let body = recv_stream
-        .next()
-        .await
-        .ok_or_else(|| anyhow!("unexpected EOF"))??;
+    let body = next_frame_with_idle_timeout(recv_stream, idle_timeout).await?;
+    let body = decompress_body(
+        &mut request_header.headers,
+        body.freeze(),
+        recv_stream.decoder().max_frame_length(),
+    )?;
 
-    let request = Request::from_parts(request_header, body.freeze());
+    let request = Request::from_parts(request_header, body);
 
     Ok(request)
 }
 
+/// Encode `request`, including its version, header and body frames, as a single buffer which can
+/// be sent as one frame on a stream carrying a sequence of requests.
+pub(crate) async fn encode_request(
+    request: Request<Bytes>,
+    codec: LengthDelimitedCodec,
+    compression: Option<&CompressionConfig>,
+) -> Result<Bytes> {
+    let mut buf = FramedWrite::new(Vec::new(), codec);
+    write_request(&mut buf, request, compression).await?;
+    Ok(buf.into_inner().into())
+}
+
+/// Decode a request previously encoded with [`encode_request`].
+pub(crate) async fn decode_request(
+    buf: &[u8],
+    codec: LengthDelimitedCodec,
+    idle_timeout: Duration,
+) -> Result<Request<Bytes>> {
+    read_request(&mut FramedRead::new(buf, codec), idle_timeout).await
+}
+
+/// Read the next frame from `recv_stream`.
+///
+/// If the stream ends before a complete frame has been read, whether before the frame started or
+/// partway through it, an [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) error is returned.
+async fn next_frame<T: AsyncRead + Unpin>(
+    recv_stream: &mut FramedRead<T, LengthDelimitedCodec>,
+) -> io::Result<BytesMut> {
+    let frame = recv_stream.next().await;
+    let partial = recv_stream.read_buffer().len();
+    match frame {
+        Some(Ok(frame)) => Ok(frame),
+        // `FramedRead` reports bytes left over once the stream has ended as an `Other` error
+        Some(Err(e)) if e.kind() == io::ErrorKind::Other && partial > 0 => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("stream closed partway through a frame, after {partial} bytes"),
+        )),
+        Some(Err(e)) => Err(e),
+        None => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "stream closed before a complete frame was read",
+        )),
+    }
+}
+
+/// Read the next frame from `recv_stream`, failing if no data arrives for `idle_timeout`.
+///
+/// Unlike a timeout on reading the entire frame, large frames which are arriving slowly but
+/// steadily are not interrupted.
+async fn next_frame_with_idle_timeout<T: AsyncRead + Unpin>(
+    recv_stream: &mut FramedRead<T, LengthDelimitedCodec>,
+    idle_timeout: Duration,
+) -> Result<BytesMut> {
+    let mut buffered = recv_stream.read_buffer().len();
+    loop {
+        tokio::select! {
+            frame = next_frame(recv_stream) => {
+                return Ok(frame?);
+            }
+            _ = tokio::time::sleep(idle_timeout) => {
+                // Data arriving for a partially read frame accumulates in the read buffer, so
+                // only give up if the buffer hasn't grown since the last check
+                let now_buffered = recv_stream.read_buffer().len();
+                if now_buffered == buffered {
+                    bail!("no data received on stream for {idle_timeout:?}");
+                }
+                buffered = now_buffered;
+            }
+        }
+    }
+}
+
 pub(crate) async fn read_response<T: AsyncRead + Unpin>(
     recv_stream: &mut FramedRead<T, LengthDelimitedCodec>,
 ) -> Result<Response<Bytes>> {
@@ -159,27 +549,33 @@ pub(crate) async fn read_response<T: AsyncRead + Unpin>(
     let version = read_version_frame(recv_stream.get_mut()).await?;
 
     // Read Request Header
-    let header_buf = recv_stream
-        .next()
-        .await
-        .ok_or_else(|| anyhow!("unexpected EOF"))??;
+    let header_buf = next_frame(recv_stream).await?;
     let raw_header: RawResponseHeader = bincode::deserialize(&header_buf)?;
-    let response_header = ResponseHeader::from_raw(raw_header, version)?;
+    let mut response_header = ResponseHeader::from_raw(raw_header, version)?;
 
     // Read Body
-    let body = recv_stream
-        .next()
-        .await
-        .ok_or_else(|| anyhow!("unexpected EOF"))??;
+    let body = next_frame(recv_stream).await?;
+    let body = decompress_body(
+        &mut response_header.headers,
+        body.freeze(),
+        recv_stream.decoder().max_frame_length(),
+    )?;
 
-    let response = Response::from_parts(response_header, body.freeze());
+    let response = Response::from_parts(response_header, body);
 
     Ok(response)
 }
 
 #[cfg(test)]
 mod test {
-    use super::{read_version_frame, write_version_frame, Version};
+    use super::{
+        frame_chunks, negotiate_version, network_message_frame_codec, read_hello, read_request,
+        read_version_frame, write_hello, write_request, write_version_frame, Hello, Version,
+    };
+    use crate::{config::CompressionConfig, types::header, Config, Request, RpcError};
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use tokio_util::codec::{FramedRead, FramedWrite};
 
     const HEADER: [u8; 8] = [b'a', b'n', b'e', b'm', b'o', 0, 1, 0];
 
@@ -223,4 +619,181 @@ mod test {
         write_version_frame(&mut buf, Version::V1).await.unwrap();
         assert_eq!(HEADER.as_ref(), buf);
     }
+
+    #[tokio::test]
+    async fn hello_round_trip() {
+        let hello = Hello {
+            version: 1,
+            capabilities: vec!["/Greeter/SayHello".to_owned()],
+            network_id: Some([7; 32]),
+        };
+        let mut buf = Vec::new();
+        write_version_frame(&mut buf, Version::V1).await.unwrap();
+        write_hello(&mut buf, &hello).await.unwrap();
+
+        // The version frame comes first, unchanged, so that older peers can still read it
+        assert_eq!(HEADER.as_ref(), &buf[..8]);
+        let mut recv_stream = &buf[8..];
+        assert_eq!(
+            Some(hello),
+            read_hello(&mut recv_stream, 1024).await.unwrap()
+        );
+
+        // Older peers send nothing after the version frame
+        assert_eq!(None, read_hello(&mut &HEADER[8..], 1024).await.unwrap());
+
+        // Oversized hellos are refused
+        read_hello(&mut &buf[8..], 8).await.unwrap_err();
+    }
+
+    #[test]
+    fn negotiate_versions() {
+        assert_eq!(Some(Version::V1), negotiate_version(1, 1));
+
+        // A peer with a newer version downgrades to ours
+        assert_eq!(Some(Version::V1), negotiate_version(1, 7));
+        assert_eq!(Some(Version::V1), negotiate_version(7, 1));
+
+        // Versions we don't support are refused
+        assert_eq!(None, negotiate_version(1, 0));
+        assert_eq!(None, negotiate_version(0, 1));
+    }
+
+    async fn encode_request(
+        request: Request<Bytes>,
+        compression: Option<&CompressionConfig>,
+    ) -> Vec<u8> {
+        let codec = network_message_frame_codec(&Config::default());
+        let mut send_stream = FramedWrite::new(Vec::new(), codec);
+        write_request(&mut send_stream, request, compression)
+            .await
+            .unwrap();
+        send_stream.into_inner()
+    }
+
+    async fn decode_request(buf: &[u8]) -> Request<Bytes> {
+        let codec = network_message_frame_codec(&Config::default());
+        let mut recv_stream = FramedRead::new(buf, codec);
+        read_request(&mut recv_stream, Config::default().stream_idle_timeout())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn compressed_body_round_trip() {
+        let compression = CompressionConfig::default();
+        let body = Bytes::from("The Stormlight Archive ".repeat(1024));
+
+        let uncompressed = encode_request(Request::new(body.clone()), None).await;
+        let compressed = encode_request(Request::new(body.clone()), Some(&compression)).await;
+        assert!(compressed.len() < uncompressed.len());
+
+        // Both forms decode to the same body, without exposing the compression header
+        for buf in [uncompressed, compressed] {
+            let request = decode_request(&buf).await;
+            assert_eq!(request.body(), &body);
+            assert!(!request.headers().contains_key(header::BODY_COMPRESSION));
+        }
+    }
+
+    #[tokio::test]
+    async fn small_body_is_not_compressed() {
+        let compression = CompressionConfig {
+            min_size: Some(1024),
+            ..Default::default()
+        };
+        let body = Bytes::from("a".repeat(512));
+
+        let buf = encode_request(Request::new(body.clone()), Some(&compression)).await;
+        let uncompressed = encode_request(Request::new(body.clone()), None).await;
+        assert_eq!(buf, uncompressed);
+        assert_eq!(decode_request(&buf).await.body(), &body);
+    }
+
+    #[tokio::test]
+    async fn reject_oversized_frame() {
+        let config = Config {
+            max_frame_size: Some(1024),
+            ..Default::default()
+        };
+
+        // A header frame declaring a length far larger than the maximum, without any payload
+        let mut buf = HEADER.to_vec();
+        buf.extend_from_slice(&u32::MAX.to_be_bytes());
+
+        let mut recv_stream = FramedRead::new(buf.as_slice(), network_message_frame_codec(&config));
+        read_request(&mut recv_stream, config.stream_idle_timeout())
+            .await
+            .unwrap_err();
+    }
+
+    #[test]
+    fn sliced_body_is_not_copied() {
+        let config = Config::default();
+        let codec = network_message_frame_codec(&config);
+
+        // A large response body sliced out of a buffer shared with other responses
+        let shared = Bytes::from(vec![7u8; 4 << 20]);
+        let body = shared.slice(1 << 20..3 << 20);
+
+        let [len, framed_body] = frame_chunks(body.clone(), &codec).unwrap();
+        assert_eq!(len.as_ref(), (2u32 << 20).to_be_bytes());
+        assert_eq!(framed_body.as_ptr(), body.as_ptr());
+        assert_eq!(framed_body.as_ptr(), shared[1 << 20..].as_ptr());
+
+        let too_big = Config {
+            max_frame_size: Some(1 << 20),
+            ..Default::default()
+        };
+        frame_chunks(body, &network_message_frame_codec(&too_big)).unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn frame_chunks_round_trip() {
+        let config = Config::default();
+        let codec = network_message_frame_codec(&config);
+
+        let bodies = [
+            Bytes::new(),
+            Bytes::from_static(b"hello"),
+            Bytes::from(vec![7u8; 1 << 20]),
+        ];
+        let mut buf = Vec::new();
+        for body in &bodies {
+            for chunk in frame_chunks(body.clone(), &codec).unwrap() {
+                buf.extend_from_slice(&chunk);
+            }
+        }
+
+        let mut recv_stream = FramedRead::new(buf.as_slice(), codec);
+        for body in bodies {
+            assert_eq!(recv_stream.next().await.unwrap().unwrap(), body);
+        }
+        assert!(recv_stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn truncated_frame() {
+        let buf = encode_request(Request::new(Bytes::from(vec![1u8; 1024])), None).await;
+
+        // Cut the stream off partway through the body, right after the body's length prefix,
+        // between the header and the body, and partway through the version frame
+        for len in [buf.len() - 10, buf.len() - 1024, buf.len() - 1028, 4] {
+            let codec = network_message_frame_codec(&Config::default());
+            let mut recv_stream = FramedRead::new(&buf[..len], codec);
+            let error = read_request(&mut recv_stream, Config::default().stream_idle_timeout())
+                .await
+                .unwrap_err();
+
+            assert_eq!(
+                error.downcast_ref::<std::io::Error>().unwrap().kind(),
+                std::io::ErrorKind::UnexpectedEof,
+                "{error}"
+            );
+            assert!(matches!(
+                RpcError::from_stream_error(error),
+                RpcError::RemoteClosed
+            ));
+        }
+    }
 }
@@ -1,9 +1,9 @@
 use crate::{Request, Response};
 use bytes::Bytes;
-use std::{collections::HashMap, convert::Infallible, fmt, sync::Arc};
+use std::{collections::HashMap, convert::Infallible, fmt, sync::Arc, time::Duration};
 use tower::{
     util::{BoxCloneService, Oneshot},
-    Service,
+    Layer, Service, ServiceExt,
 };
 
 mod not_found;
@@ -27,12 +27,31 @@ impl RouteId {
     }
 }
 
+/// Options controlling how requests to a single route are handled.
+///
+/// See [`Router::route_with_opts`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RouteOpts {
+    /// Maximum number of requests to the route which may be handled concurrently. Further
+    /// requests wait until one of the in-flight requests completes.
+    ///
+    /// If unspecified, there is no limit.
+    pub max_concurrency: Option<usize>,
+
+    /// How long a request to the route may take, including any time spent waiting due to
+    /// `max_concurrency`, before it is responded to with
+    /// [`StatusCode::RequestTimeout`](crate::types::response::StatusCode::RequestTimeout).
+    ///
+    /// If unspecified, only the timeout requested by the caller applies.
+    pub timeout: Option<Duration>,
+}
+
 /// The router type for composing handlers and services.
 #[derive(Clone)]
 pub struct Router {
     routes: HashMap<RouteId, Route>,
     matcher: RouteMatcher,
-    fallback: Route,
+    fallback: Fallback,
 }
 
 impl Router {
@@ -41,7 +60,7 @@ impl Router {
         Self {
             routes: Default::default(),
             matcher: Default::default(),
-            fallback: Route::new(not_found::NotFound),
+            fallback: Fallback::Default(Route::new(not_found::NotFound)),
         }
     }
 
@@ -77,6 +96,53 @@ impl Router {
         self
     }
 
+    /// Add a route whose requests are subject to their own concurrency limit and timeout, so that
+    /// a slow route can't starve the others.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`Router::route`], or if `opts.max_concurrency` is
+    /// `Some(0)`.
+    pub fn route_with_opts<T>(self, path: &str, service: T, opts: RouteOpts) -> Self
+    where
+        T: Service<Request<Bytes>, Response = Response<Bytes>, Error = Infallible>
+            + Clone
+            + Send
+            + 'static,
+        T::Future: Send + 'static,
+    {
+        if <dyn std::any::Any>::downcast_ref::<Self>(&service).is_some() {
+            panic!("Invalid route: `Router::route` cannot be used with `Router`s.")
+        }
+        if opts.max_concurrency == Some(0) {
+            panic!("Invalid route: `max_concurrency` must be nonzero");
+        }
+
+        let limiter = opts
+            .max_concurrency
+            .map(|max| Arc::new(tokio::sync::Semaphore::new(max)));
+        let service = tower::service_fn(move |request: Request<Bytes>| {
+            let limiter = limiter.clone();
+            let service = service.clone();
+            async move {
+                let _permit = match limiter {
+                    Some(limiter) => Some(
+                        limiter
+                            .acquire_owned()
+                            .await
+                            .expect("semaphore is never closed"),
+                    ),
+                    None => None,
+                };
+                service.oneshot(request).await
+            }
+        });
+        let service =
+            crate::middleware::timeout::inbound::TimeoutLayer::new(opts.timeout).layer(service);
+
+        self.route(path, service)
+    }
+
     pub fn add_rpc_service<S>(self, service: S) -> Self
     where
         S: Service<Request<Bytes>, Response = Response<Bytes>, Error = Infallible>
@@ -90,10 +156,46 @@ impl Router {
         self.route(&path, service)
     }
 
+    /// Returns the paths of all routes registered with this router, in sorted order.
+    ///
+    /// These are advertised to peers as the network's capabilities when this router is the
+    /// service provided to [`Builder::start`](crate::Builder::start).
+    pub fn route_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .matcher
+            .route_id_to_path
+            .values()
+            .map(|path| path.to_string())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Add a fallback service to the router.
+    ///
+    /// This service will be called for any request which doesn't match a registered route. If no
+    /// fallback is configured, such requests are responded to with
+    /// [`StatusCode::NotFound`](crate::types::response::StatusCode::NotFound).
+    pub fn fallback<T>(mut self, service: T) -> Self
+    where
+        T: Service<Request<Bytes>, Response = Response<Bytes>, Error = Infallible>
+            + Clone
+            + Send
+            + 'static,
+        T::Future: Send + 'static,
+    {
+        self.fallback = Fallback::Custom(Route::new(service));
+        self
+    }
+
     /// Merge two routers into one.
     ///
     /// This is useful for breaking apps into smaller pieces and combining them
     /// into one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if both routers have a custom fallback configured.
     pub fn merge<R>(mut self, other: R) -> Self
     where
         R: Into<Router>,
@@ -112,9 +214,14 @@ impl Router {
             self = self.route(path, route);
         }
 
-        // TODO if we support custom fallback functions in the future we need to make sure to check
-        // that we dont try to merge two routers that both have custom fallbacks
-        let _fallback = fallback;
+        self.fallback = match (self.fallback, fallback) {
+            (Fallback::Default(_), pick @ Fallback::Default(_)) => pick,
+            (Fallback::Default(_), pick @ Fallback::Custom(_)) => pick,
+            (pick @ Fallback::Custom(_), Fallback::Default(_)) => pick,
+            (Fallback::Custom(_), Fallback::Custom(_)) => {
+                panic!("Cannot merge two `Router`s that both have a fallback")
+            }
+        };
 
         self
     }
@@ -182,7 +289,22 @@ impl Service<Request<Bytes>> for Router {
             }
             Err(MatchError::MissingTrailingSlash)
             | Err(MatchError::ExtraTrailingSlash)
-            | Err(MatchError::NotFound) => self.fallback.oneshot_inner(req),
+            | Err(MatchError::NotFound) => self.fallback.route().oneshot_inner(req),
+        }
+    }
+}
+
+/// The service requests are routed to when they don't match any registered route.
+#[derive(Clone)]
+enum Fallback {
+    Default(Route),
+    Custom(Route),
+}
+
+impl Fallback {
+    fn route(&self) -> &Route {
+        match self {
+            Fallback::Default(route) | Fallback::Custom(route) => route,
         }
     }
 }
@@ -349,6 +471,41 @@ mod test {
         assert_eq!(response.status(), StatusCode::NotFound);
     }
 
+    #[tokio::test]
+    async fn router_with_fallback() {
+        let hello_world = tower::service_fn(|_request| async {
+            Ok(Response::new(Bytes::from_static(b"hello world!")))
+        });
+
+        let fallback = tower::service_fn(|_request| async {
+            Ok(Response::new(Bytes::from_static(b"fallback")))
+        });
+
+        let router = Router::new()
+            .route("/echo", echo_service())
+            .route("/hello-world", hello_world)
+            .fallback(fallback);
+
+        // Echo
+        let msg = b"echo this text";
+        let request = Request::new(Bytes::from_static(msg)).with_route("/echo");
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::Success);
+        assert_eq!(response.body(), msg.as_ref());
+
+        // Hello World
+        let request = Request::new(Bytes::new()).with_route("/hello-world");
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::Success);
+        assert_eq!(response.body(), "hello world!");
+
+        // Unknown routes reach the fallback
+        let request = Request::new(Bytes::new()).with_route("/unknown");
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::Success);
+        assert_eq!(response.body(), "fallback");
+    }
+
     fn echo_service() -> BoxCloneService<Request<Bytes>, Response<Bytes>, Infallible> {
         let handle = move |request: Request<Bytes>| async move {
             trace!("recieved: {}", request.body().escape_ascii());
@@ -383,6 +540,30 @@ mod test {
         assert_eq!(response.body(), "hello world!");
     }
 
+    #[tokio::test]
+    async fn merge_router_keeps_fallback() {
+        let fallback = tower::service_fn(|_request| async {
+            Ok(Response::new(Bytes::from_static(b"fallback")))
+        });
+
+        let echo_router = Router::new()
+            .route("/echo", echo_service())
+            .fallback(fallback);
+        let router = Router::new().merge(echo_router);
+
+        let request = Request::new(Bytes::new()).with_route("/unknown");
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.body(), "fallback");
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot merge two `Router`s that both have a fallback")]
+    fn merge_router_with_conflicting_fallbacks() {
+        let router_1 = Router::new().fallback(echo_service());
+        let router_2 = Router::new().fallback(echo_service());
+        router_1.merge(router_2);
+    }
+
     #[test]
     #[should_panic(
         expected = "Invalid route: insertion failed due to conflict with previously registered route"
@@ -409,6 +590,85 @@ mod test {
         assert_eq!(super::try_downcast::<i32, _>(5_i32), Ok(5_i32));
     }
 
+    #[tokio::test]
+    async fn route_with_opts_timeout() {
+        let pending =
+            tower::service_fn(|_request| async { std::future::pending::<Result<_, _>>().await });
+        let opts = RouteOpts {
+            timeout: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let router = Router::new()
+            .route_with_opts("/slow", pending, opts)
+            .route_with_opts("/fast", echo_service(), opts);
+
+        let request = Request::new(Bytes::new()).with_route("/slow");
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::RequestTimeout);
+
+        let request = Request::new(Bytes::from_static(b"fast")).with_route("/fast");
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::Success);
+        assert_eq!(response.body(), "fast");
+    }
+
+    #[tokio::test]
+    async fn route_with_opts_max_concurrency() {
+        let release = Arc::new(tokio::sync::Notify::new());
+        let blocking = {
+            let release = release.clone();
+            tower::service_fn(move |request: Request<Bytes>| {
+                let release = release.clone();
+                async move {
+                    release.notified().await;
+                    Ok(Response::new(request.into_body()))
+                }
+            })
+        };
+        let router = Router::new()
+            .route_with_opts(
+                "/slow",
+                blocking,
+                RouteOpts {
+                    max_concurrency: Some(1),
+                    timeout: Some(Duration::from_millis(100)),
+                },
+            )
+            .route("/fast", echo_service());
+
+        // Occupy the only slot of the slow route
+        let request = Request::new(Bytes::from_static(b"first")).with_route("/slow");
+        let first = tokio::spawn(router.clone().oneshot(request));
+        tokio::task::yield_now().await;
+
+        // Requests which can't get a slot time out
+        let request = Request::new(Bytes::new()).with_route("/slow");
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::RequestTimeout);
+
+        // Other routes are unaffected
+        let request = Request::new(Bytes::from_static(b"fast")).with_route("/fast");
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::Success);
+
+        release.notify_one();
+        let response = first.await.unwrap().unwrap();
+        assert_eq!(response.body(), "first");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid route: `max_concurrency` must be nonzero")]
+    fn route_with_opts_zero_concurrency() {
+        Router::new().route_with_opts(
+            "/echo",
+            echo_service(),
+            RouteOpts {
+                max_concurrency: Some(0),
+                ..Default::default()
+            },
+        );
+    }
+
     #[tokio::test]
     async fn middleware_applies_to_routes_above() {
         let pending =
@@ -0,0 +1,101 @@
+//! A ready-made health check service.
+//!
+//! Add a [`HealthService`] to a [`Router`](crate::Router) via
+//! [`Router::add_rpc_service`](crate::Router::add_rpc_service) and peers can then check on the
+//! health of this node via [`Peer::check_health`](crate::Peer::check_health).
+
+use crate::{
+    types::response::{IntoResponse, StatusCode},
+    NetworkRef, PeerId, Request, Response,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, time::Duration, time::Instant};
+use tower::Service;
+
+/// The route that health checks are served on.
+pub const HEALTH_CHECK_ROUTE: &str = "/anemo.Health/Check";
+
+/// The response to a health check.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthResponse {
+    /// The [`PeerId`] of the responding node.
+    pub peer_id: PeerId,
+
+    /// How long the responding node's [`HealthService`] has been running.
+    pub uptime: Duration,
+
+    /// Number of peers the responding node currently has an active connection with.
+    pub peer_count: usize,
+}
+
+/// A [`Service`] which responds to health checks on [`HEALTH_CHECK_ROUTE`].
+///
+/// Responses are [`HealthResponse`]s encoded with bincode. Any other route under the
+/// `anemo.Health` service is responded to with [`StatusCode::NotFound`].
+#[derive(Clone, Debug)]
+pub struct HealthService {
+    started: Instant,
+}
+
+impl HealthService {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+
+    fn check(&self, request: &Request<Bytes>) -> Result<HealthResponse, StatusCode> {
+        if request.route() != HEALTH_CHECK_ROUTE {
+            return Err(StatusCode::NotFound);
+        }
+
+        let network = request
+            .extensions()
+            .get::<NetworkRef>()
+            .and_then(NetworkRef::upgrade)
+            .ok_or(StatusCode::InternalServerError)?;
+
+        Ok(HealthResponse {
+            peer_id: network.peer_id(),
+            uptime: self.started.elapsed(),
+            peer_count: network.peers().len(),
+        })
+    }
+}
+
+impl Default for HealthService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::RpcService for HealthService {
+    const SERVICE_NAME: &'static str = "anemo.Health";
+}
+
+impl Service<Request<Bytes>> for HealthService {
+    type Response = Response<Bytes>;
+    type Error = Infallible;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Bytes>) -> Self::Future {
+        let response = match self.check(&request) {
+            Ok(health) => match bincode::serialize(&health) {
+                Ok(body) => Response::new(Bytes::from(body)),
+                Err(_) => StatusCode::InternalServerError.into_response(),
+            },
+            Err(status) => status.into_response(),
+        };
+
+        std::future::ready(Ok(response))
+    }
+}
@@ -8,6 +8,8 @@ use crate::{
 };
 
 pub mod codec;
+pub mod health;
+pub mod typed;
 
 #[derive(Debug)]
 pub struct Status {
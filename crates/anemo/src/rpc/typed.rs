@@ -0,0 +1,184 @@
+//! Helpers for RPCs whose requests and responses are typed messages rather than raw bytes.
+//!
+//! [`TypedPeer`] encodes requests and decodes responses around [`Peer::rpc`], while
+//! [`TypedService`] adapts a service handling typed messages so that it can be added to a
+//! [`Router`](crate::Router). Both use a [`Codec`], [`BincodeCodec`] by default, to convert
+//! messages to and from bytes.
+
+use super::{
+    codec::{BincodeCodec, Codec, Decoder, Encoder},
+    server::{self, UnaryService},
+};
+use crate::{
+    error::BoxError,
+    types::{header, response::StatusCode},
+    Peer, Request, Response, RpcError,
+};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use std::{convert::Infallible, marker::PhantomData};
+use tower::Service;
+
+/// Error returned by [`TypedPeer::rpc_typed`].
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum TypedRpcError {
+    /// The request could not be encoded.
+    #[error("unable to encode request: {0}")]
+    Encode(BoxError),
+
+    /// The RPC itself failed.
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+
+    /// The peer responded with an unsuccessful status.
+    #[error("rpc failed with status {status}")]
+    Status {
+        status: StatusCode,
+        message: Option<String>,
+    },
+
+    /// The response from the peer could not be decoded.
+    #[error("unable to decode response: {0}")]
+    Decode(BoxError),
+}
+
+/// A [`Peer`] which sends `Req` messages to a single route and receives `Resp` messages in
+/// response.
+pub struct TypedPeer<Req, Resp, C = BincodeCodec<Req, Resp>> {
+    peer: Peer,
+    route: String,
+    _marker: PhantomData<fn(Req, C) -> Resp>,
+}
+
+impl<Req, Resp, C> TypedPeer<Req, Resp, C>
+where
+    C: Codec<Encode = Req, Decode = Resp> + Default,
+{
+    /// Create a `TypedPeer` which sends requests to `route` on `peer`.
+    pub fn new<R: Into<String>>(peer: Peer, route: R) -> Self {
+        Self {
+            peer,
+            route: route.into(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Gets a reference to the underlying peer.
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
+    /// Consumes `self`, returning the underlying peer.
+    pub fn into_inner(self) -> Peer {
+        self.peer
+    }
+
+    /// Encode `request`, send it to the peer and decode its response.
+    pub async fn rpc_typed(&self, request: Req) -> Result<Resp, TypedRpcError> {
+        let mut codec = C::default();
+
+        let body = codec
+            .encoder()
+            .encode(request)
+            .map_err(|e| TypedRpcError::Encode(e.into()))?;
+        let mut request = Request::new(body).with_route(self.route.clone());
+        request.headers_mut().insert(
+            header::CONTENT_TYPE.to_owned(),
+            codec.format_name().to_owned(),
+        );
+
+        let response = self.peer.clone().rpc(request).await?;
+
+        if !response.status().is_success() {
+            return Err(TypedRpcError::Status {
+                status: response.status(),
+                message: response.headers().get(header::STATUS_MESSAGE).cloned(),
+            });
+        }
+
+        codec
+            .decoder()
+            .decode(response.into_body())
+            .map_err(|e| TypedRpcError::Decode(e.into()))
+    }
+}
+
+impl<Req, Resp, C> Clone for TypedPeer<Req, Resp, C> {
+    fn clone(&self) -> Self {
+        Self {
+            peer: self.peer.clone(),
+            route: self.route.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Adapts a service handling `Req` messages and responding with `Resp` messages into one which
+/// can be added to a [`Router`](crate::Router).
+///
+/// Requests which can't be decoded are responded to with an error status without calling the
+/// inner service.
+pub struct TypedService<S, Req, Resp, C = BincodeCodec<Resp, Req>> {
+    inner: S,
+    _marker: PhantomData<fn(Req, C) -> Resp>,
+}
+
+impl<S, Req, Resp, C> TypedService<S, Req, Resp, C>
+where
+    S: UnaryService<Req, Response = Resp>,
+    C: Codec<Encode = Resp, Decode = Req> + Default,
+{
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Gets a reference to the underlying service.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the underlying service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Clone, Req, Resp, C> Clone for TypedService<S, Req, Resp, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, Req, Resp, C> Service<Request<Bytes>> for TypedService<S, Req, Resp, C>
+where
+    S: UnaryService<Req, Response = Resp> + Clone + Send + 'static,
+    S::Future: Send,
+    C: Codec<Encode = Resp, Decode = Req> + Default + Send + 'static,
+{
+    type Response = Response<Bytes>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Bytes>) -> Self::Future {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let mut rpc = server::Rpc::new(C::default(), C::default());
+            Ok(rpc.unary(inner, request).await)
+        })
+    }
+}
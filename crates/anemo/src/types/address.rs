@@ -1,5 +1,6 @@
 /// Representation of a network address that is dial-able in Anemo
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Address {
     /// A plain SocketAddr
     SocketAddr(std::net::SocketAddr),
@@ -21,6 +22,20 @@ impl Address {
             })
         })
     }
+
+    /// Resolve this address without blocking, performing any DNS lookup on tokio's blocking pool.
+    pub(crate) async fn lookup(&self) -> std::io::Result<std::net::SocketAddr> {
+        let addr = match self {
+            Address::SocketAddr(addr) => Some(*addr),
+            Address::HostAndPort { host, port } => tokio::net::lookup_host((host.as_ref(), *port))
+                .await?
+                .next(),
+            Address::AddressString(addr) => tokio::net::lookup_host(addr.as_ref()).await?.next(),
+        };
+        addr.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "unable to resolve host")
+        })
+    }
 }
 
 impl std::fmt::Display for Address {
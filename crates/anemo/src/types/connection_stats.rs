@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+/// A snapshot of the health of a connection with a peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub struct ConnectionStats {
+    /// Current best estimate of the connection's round-trip-time.
+    pub rtt: Duration,
+
+    /// Total number of bytes sent over the connection, including protocol overhead.
+    pub bytes_sent: u64,
+
+    /// Total number of bytes received over the connection, including protocol overhead.
+    pub bytes_received: u64,
+
+    /// Current congestion window of the connection, in bytes.
+    pub congestion_window: u64,
+
+    /// Total number of packets sent over the connection.
+    pub sent_packets: u64,
+
+    /// Total number of packets which were deemed lost.
+    pub lost_packets: u64,
+
+    /// Whether the connection resumed a previous TLS session with the peer.
+    ///
+    /// See [`Config::enable_session_resumption`](crate::Config::enable_session_resumption).
+    pub resumed: bool,
+}
+
+impl From<quinn_proto::ConnectionStats> for ConnectionStats {
+    fn from(stats: quinn_proto::ConnectionStats) -> Self {
+        Self {
+            rtt: stats.path.rtt,
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            congestion_window: stats.path.cwnd,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+            resumed: false,
+        }
+    }
+}
+
+/// A snapshot of aggregate statistics for a [`Network`](crate::Network)'s endpoint, covering all
+/// connections over the lifetime of the network.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[non_exhaustive]
+pub struct EndpointStats {
+    /// Total number of inbound connections which were accepted.
+    pub accepted_connections: u64,
+
+    /// Total number of inbound connections which were rejected or failed to be established, for
+    /// example due to connection limits or a failed handshake.
+    pub rejected_connections: u64,
+
+    /// Number of peers which currently have an established connection.
+    pub active_connections: u64,
+
+    /// Total number of datagrams sent via [`Peer::send_datagram`](crate::Peer::send_datagram).
+    pub datagrams_sent: u64,
+
+    /// Total number of datagrams received from peers.
+    pub datagrams_received: u64,
+
+    /// Total number of received datagrams which were dropped because a receiver returned by
+    /// [`Network::datagrams`](crate::Network::datagrams) was full. A datagram dropped by several
+    /// receivers is counted once for each of them.
+    pub datagrams_dropped: u64,
+}
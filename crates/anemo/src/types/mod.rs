@@ -1,21 +1,28 @@
 mod address;
+mod connection_stats;
 mod peer_id;
 pub mod request;
 pub mod response;
 
 pub use address::Address;
-pub use peer_id::{ConnectionOrigin, Direction, PeerId};
+pub use connection_stats::{ConnectionStats, EndpointStats};
+pub use peer_id::{ConnectionOrigin, Direction, ParsePeerIdError, PeerId};
 
 pub use http::Extensions;
 use quinn::ConnectionError;
+pub use tokio_util::sync::CancellationToken;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u16)]
 pub enum Version {
+    #[default]
     V1 = 1,
 }
 
 impl Version {
+    /// The most recent version of the wire protocol.
+    pub const LATEST: Version = Version::V1;
+
     pub fn new(version: u16) -> crate::Result<Self> {
         match version {
             1 => Ok(Version::V1),
@@ -28,12 +35,6 @@ impl Version {
     }
 }
 
-impl Default for Version {
-    fn default() -> Self {
-        Self::V1
-    }
-}
-
 pub type HeaderMap = std::collections::HashMap<String, String>;
 
 pub mod header {
@@ -41,19 +42,42 @@ pub mod header {
     pub const STATUS_MESSAGE: &str = "status-message";
     /// Timeout in nanoseconds, encoded as an u64
     pub const TIMEOUT: &str = "timeout";
+    /// Marks a request as the start of a streaming RPC
+    pub const STREAMING: &str = "streaming";
+    /// Marks a streaming RPC as one where only the server streams frames back to the client
+    pub const SERVER_STREAMING: &str = "server-streaming";
+    /// Compression algorithm applied to the body of a request or response
+    pub const BODY_COMPRESSION: &str = "body-compression";
+    /// Marks a request as safe to send more than once
+    pub const IDEMPOTENT: &str = "idempotent";
 }
 
-#[derive(Clone, Copy, Debug)]
+/// How strongly we want to stay connected to a peer in [`KnownPeers`](crate::KnownPeers).
+///
+/// Only peers with [`PeerAffinity::High`] are dialed, and redialed with backoff, in the
+/// background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PeerAffinity {
     /// Always attempt to maintain a connection with this Peer.
+    ///
+    /// If the connection is lost it will be re-established in the background, backing off as
+    /// described by [`Config::reconnect`](crate::Config#structfield.reconnect).
     High,
+    /// Accept connections from this Peer but don't attempt to maintain a connection with it.
+    ///
+    /// This is the low affinity counterpart to [`PeerAffinity::High`]: the peer is known, and
+    /// is preferred over unknown peers when evicting, but it is never reconnected to in the
+    /// background.
+    Allowed,
     /// Never attempt to maintain a connection with this Peer.
     ///
     /// Inbound connection requests from these Peers are rejected.
     Never,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct PeerInfo {
     pub peer_id: PeerId,
     pub affinity: PeerAffinity,
@@ -62,32 +86,181 @@ pub struct PeerInfo {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PeerEvent {
-    NewPeer(PeerId),
-    LostPeer(PeerId, DisconnectReason),
+    /// A connection was established with a peer, either by dialing it
+    /// ([`ConnectionOrigin::Outbound`]) or by accepting its connection
+    /// ([`ConnectionOrigin::Inbound`]).
+    NewPeer(PeerId, ConnectionOrigin),
+    /// The connection with a peer was lost. The [`DisconnectCause`] describes what happened in
+    /// more detail than the [`DisconnectReason`], when known.
+    LostPeer(PeerId, DisconnectReason, DisconnectCause),
+}
+
+impl PeerEvent {
+    /// The peer this event is about.
+    pub fn peer_id(&self) -> PeerId {
+        match self {
+            PeerEvent::NewPeer(peer_id, _) | PeerEvent::LostPeer(peer_id, _, _) => *peer_id,
+        }
+    }
+}
+
+/// A snapshot of an active connection with a peer.
+///
+/// See [`Network::peer_infos`](crate::Network::peer_infos).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivePeerInfo {
+    pub peer_id: PeerId,
+    /// The address of the remote side of the connection.
+    pub address: std::net::SocketAddr,
+    /// When the connection was established.
+    pub connected_since: std::time::Instant,
+    /// When a request or response was last exchanged with the peer, see
+    /// [`Peer::last_activity`](crate::Peer::last_activity).
+    pub last_activity: std::time::Instant,
+    /// The peer's affinity, or `None` if it isn't a known peer.
+    pub affinity: Option<PeerAffinity>,
+    /// Whether the connection was accepted or dialed by this node.
+    pub origin: ConnectionOrigin,
+}
+
+/// A non-fatal error encountered while accepting an inbound connection, e.g. due to a failed
+/// handshake or the connection being refused.
+///
+/// See [`Network::subscribe_accept_errors`](crate::Network::subscribe_accept_errors).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceptError {
+    /// The address of the remote side of the connection.
+    pub address: std::net::SocketAddr,
+    /// A description of why the connection could not be accepted.
+    pub error: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DisconnectReason {
+    /// The connection was closed locally via [`Network::disconnect`](crate::Network::disconnect).
     Requested,
+    /// The peer doesn't support any of our QUIC versions, or any of our wire protocol versions.
     VersionMismatch,
-    TransportError,
+    /// The connection was closed due to a QUIC protocol error, described by the contained message.
+    TransportError(String),
+    /// The peer closed the connection due to a QUIC protocol error.
     ConnectionClosed,
+    /// The peer closed the connection.
     ApplicationClosed,
+    /// The peer closed the connection with an application error `code`.
+    ApplicationError { code: u64 },
+    /// The peer is unable to continue processing the connection, usually due to having restarted.
     Reset,
-    TimedOut,
+    /// Nothing was heard from the peer within the configured idle timeout.
+    IdleTimeout,
+    /// The connection was closed locally.
     LocallyClosed,
+    /// The peer was disconnected to make room for a peer with a higher affinity.
+    Evicted,
+    /// The peer was disconnected because it was banned, either by us or by the peer.
+    Banned,
+    /// The connection was refused because it was made with ourself, e.g. as a result of dialing
+    /// our own address.
+    SelfConnection,
+    /// Another connection with the peer was established, e.g. as a result of both peers dialing
+    /// each other simultaneously, and this one was closed in its favor.
+    DuplicateConnection,
+    /// The connection was refused because the peer is in drain mode, see
+    /// [`Network::enter_drain_mode`](crate::Network::enter_drain_mode).
+    Draining,
+    /// The connection was refused because the peers belong to different networks, see
+    /// [`Config::network_id`](crate::Config::network_id).
+    NetworkMismatch,
 }
 
 impl DisconnectReason {
     pub fn from_quinn_error(error: &ConnectionError) -> Self {
+        use crate::connection::close_code;
+
         match error {
             ConnectionError::VersionMismatch => DisconnectReason::VersionMismatch,
-            ConnectionError::TransportError(_) => DisconnectReason::TransportError,
+            ConnectionError::TransportError(e) => DisconnectReason::TransportError(e.to_string()),
             ConnectionError::ConnectionClosed(_) => DisconnectReason::ConnectionClosed,
-            ConnectionError::ApplicationClosed(_) => DisconnectReason::ApplicationClosed,
+            ConnectionError::ApplicationClosed(close) => {
+                let code = close.error_code.into_inner();
+                if code == u64::from(close_code::CLOSED) {
+                    DisconnectReason::ApplicationClosed
+                } else if code == u64::from(close_code::BANNED) {
+                    DisconnectReason::Banned
+                } else if code == u64::from(close_code::VERSION_MISMATCH) {
+                    DisconnectReason::VersionMismatch
+                } else if code == u64::from(close_code::SELF_CONNECTION) {
+                    DisconnectReason::SelfConnection
+                } else if code == u64::from(close_code::DUPLICATE_CONNECTION) {
+                    DisconnectReason::DuplicateConnection
+                } else if code == u64::from(close_code::DRAINING) {
+                    DisconnectReason::Draining
+                } else if code == u64::from(close_code::NETWORK_MISMATCH) {
+                    DisconnectReason::NetworkMismatch
+                } else {
+                    DisconnectReason::ApplicationError { code }
+                }
+            }
             ConnectionError::Reset => DisconnectReason::Reset,
-            ConnectionError::TimedOut => DisconnectReason::TimedOut,
+            ConnectionError::TimedOut => DisconnectReason::IdleTimeout,
             ConnectionError::LocallyClosed => DisconnectReason::LocallyClosed,
         }
     }
 }
+
+/// A human-readable description of why a connection was lost, e.g. the error reported by QUIC
+/// including any reason given by the peer when closing the connection.
+///
+/// The cause is intended for debugging only and is not considered when comparing events: all
+/// causes compare equal, so [`PeerEvent`]s should be matched on their [`DisconnectReason`].
+#[derive(Debug, Clone, Default)]
+pub struct DisconnectCause(Option<String>);
+
+impl DisconnectCause {
+    pub fn from_quinn_error(error: &ConnectionError) -> Self {
+        Self(Some(error.to_string()))
+    }
+
+    /// The description of the cause, or `None` if the connection wasn't lost due to an error,
+    /// e.g. because it was closed locally.
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+impl PartialEq for DisconnectCause {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for DisconnectCause {}
+
+#[cfg(test)]
+mod test {
+    use super::{DisconnectCause, DisconnectReason};
+    use quinn::ConnectionError;
+
+    #[test]
+    fn idle_timeout_is_distinguished_from_other_losses() {
+        assert_eq!(
+            DisconnectReason::from_quinn_error(&ConnectionError::TimedOut),
+            DisconnectReason::IdleTimeout
+        );
+        assert_eq!(
+            DisconnectReason::from_quinn_error(&ConnectionError::Reset),
+            DisconnectReason::Reset
+        );
+        assert_eq!(
+            DisconnectReason::from_quinn_error(&ConnectionError::LocallyClosed),
+            DisconnectReason::LocallyClosed
+        );
+    }
+
+    #[test]
+    fn cause_is_ignored_by_equality() {
+        let cause = DisconnectCause::from_quinn_error(&ConnectionError::Reset);
+        assert_eq!(cause.as_str(), Some("reset by peer"));
+        assert_eq!(cause, DisconnectCause::default());
+    }
+}
@@ -1,10 +1,37 @@
 /// Length of a PeerId, based on the length of an ed25519 public key
 const PEER_ID_LENGTH: usize = 32;
 
+/// The identity of a peer.
+///
+/// A `PeerId` is the peer's 32-byte Ed25519 public key, as defined in
+/// [RFC 8032](https://www.rfc-editor.org/rfc/rfc8032#section-5.1.5), used as-is without any
+/// hashing. It is displayed, parsed and serialized to human readable formats as the 64 character
+/// lowercase hex encoding of those bytes, and serialized to binary formats as the raw bytes. This
+/// makes it possible to compute the `PeerId` of a peer from its public key, in any language,
+/// without ever connecting to it.
 #[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PeerId(pub [u8; PEER_ID_LENGTH]);
 
 impl PeerId {
+    /// Returns the `PeerId` of the peer with the provided Ed25519 public key.
+    ///
+    /// Returns an error if `public_key` isn't exactly 32 bytes long.
+    pub fn from_public_key(public_key: &[u8]) -> crate::Result<Self> {
+        <[u8; PEER_ID_LENGTH]>::try_from(public_key)
+            .map(Self)
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "invalid public key length {}, expected {PEER_ID_LENGTH} bytes",
+                    public_key.len()
+                )
+            })
+    }
+
+    /// Returns the Ed25519 public key of this peer.
+    pub fn public_key(&self) -> &[u8; PEER_ID_LENGTH] {
+        &self.0
+    }
+
     pub fn short_display(&self, len: u8) -> impl std::fmt::Display + '_ {
         ShortPeerId(self, len)
     }
@@ -28,6 +55,22 @@ impl std::fmt::Display for PeerId {
     }
 }
 
+impl std::str::FromStr for PeerId {
+    type Err = ParsePeerIdError;
+
+    /// Parse a `PeerId` from its hex encoding, as produced by its `Display` implementation.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        hex::FromHex::from_hex(s)
+            .map(Self)
+            .map_err(ParsePeerIdError)
+    }
+}
+
+/// Error returned when parsing a [`PeerId`] from a string fails.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("invalid peer id: {0}")]
+pub struct ParsePeerIdError(hex::FromHexError);
+
 impl std::fmt::Debug for PeerId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "PeerId({self})")
@@ -44,9 +87,7 @@ impl<'de> serde::Deserialize<'de> for PeerId {
         if deserializer.is_human_readable() {
             let s = <String>::deserialize(deserializer)?;
 
-            hex::FromHex::from_hex(s)
-                .map_err(D::Error::custom)
-                .map(Self)
+            s.parse().map_err(D::Error::custom)
         } else {
             <[u8; PEER_ID_LENGTH]>::deserialize(deserializer).map(Self)
         }
@@ -59,7 +100,7 @@ impl serde::Serialize for PeerId {
         S: serde::Serializer,
     {
         if serializer.is_human_readable() {
-            hex::encode(self.0).serialize(serializer)
+            self.to_string().serialize(serializer)
         } else {
             self.0.serialize(serializer)
         }
@@ -135,7 +176,7 @@ impl std::fmt::Display for ConnectionOrigin {
 
 #[cfg(test)]
 mod test {
-    use super::{ConnectionOrigin, Direction, PeerId};
+    use super::{ConnectionOrigin, Direction, ParsePeerIdError, PeerId};
 
     #[test]
     fn direction_debug() {
@@ -184,6 +225,68 @@ mod test {
         assert_eq!(short_str.len(), num_hex_digits as usize);
     }
 
+    #[test]
+    fn from_public_key() {
+        // Test vector 1 from RFC 8032
+        const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+        let public_key = hex::decode(PUBLIC_KEY).unwrap();
+
+        let peer_id = PeerId::from_public_key(&public_key).unwrap();
+        assert_eq!(peer_id.public_key().as_ref(), public_key.as_slice());
+        assert_eq!(peer_id.to_string(), PUBLIC_KEY);
+        assert_eq!(PUBLIC_KEY.parse::<PeerId>().unwrap(), peer_id);
+
+        PeerId::from_public_key(&public_key[..31]).unwrap_err();
+        PUBLIC_KEY[..62].parse::<PeerId>().unwrap_err();
+        "not hex".parse::<PeerId>().unwrap_err();
+    }
+
+    #[test]
+    fn display_from_str_round_trip() {
+        for _ in 0..16 {
+            let peer_id = PeerId::random();
+            let s = peer_id.to_string();
+            assert_eq!(s.len(), 64);
+            assert_eq!(s.parse::<PeerId>().unwrap(), peer_id);
+        }
+    }
+
+    #[test]
+    fn from_str_rejects_malformed_input() {
+        let valid = PeerId([42; 32]).to_string();
+
+        let malformed = [
+            String::new(),
+            valid[..63].to_owned(),
+            valid[..62].to_owned(),
+            format!("{valid}00"),
+            format!("0x{}", &valid[2..]),
+            valid.replacen('2', "g", 1),
+        ];
+        for s in malformed {
+            let error: ParsePeerIdError = s.parse::<PeerId>().unwrap_err();
+            assert!(error.to_string().starts_with("invalid peer id"));
+        }
+    }
+
+    #[test]
+    fn peer_info_json() {
+        use crate::types::{PeerAffinity, PeerInfo};
+
+        let peer_info = PeerInfo {
+            peer_id: PeerId([42; 32]),
+            affinity: PeerAffinity::High,
+            address: vec![],
+        };
+        let json = serde_json::to_string(&peer_info).unwrap();
+        assert!(json.contains(&format!("\"{}\"", peer_info.peer_id)));
+        assert_eq!(serde_json::from_str::<PeerInfo>(&json).unwrap(), peer_info);
+
+        // Malformed peer ids are rejected
+        let json = json.replace(&peer_info.peer_id.to_string(), "not a peer id");
+        serde_json::from_str::<PeerInfo>(&json).unwrap_err();
+    }
+
     #[test]
     fn test_serde_json() {
         let peer_id = PeerId::random();
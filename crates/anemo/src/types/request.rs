@@ -41,6 +41,11 @@ impl RequestHeader {
     }
 }
 
+/// Request extension holding the point in time by which the sender of an inbound request expects
+/// a response, see [`Request::deadline`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline(pub std::time::Instant);
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub(crate) struct RawRequestHeader {
     pub route: String,
@@ -102,14 +107,30 @@ impl<T> Request<T> {
         self
     }
 
+    /// Returns a reference to the typed context attached to this request.
+    ///
+    /// Extensions are local to this side of an RPC and are never sent over the wire. On outbound
+    /// requests they can be used to carry values like auth tokens or tracing context to outbound
+    /// middleware, while inbound requests are given extensions such as the sender's [`PeerId`]
+    /// before being handed to the service.
+    ///
+    /// Inbound RPCs are also given a [`CancellationToken`](super::CancellationToken) which is
+    /// cancelled if the caller gives up on the RPC or the connection is lost before a response is
+    /// sent, allowing long-running handlers, and any tasks they spawn, to stop early.
     pub fn extensions(&self) -> &Extensions {
         &self.head.extensions
     }
 
+    /// Returns a mutable reference to the typed context attached to this request.
+    ///
+    /// See [`Request::extensions`] for more details.
     pub fn extensions_mut(&mut self) -> &mut Extensions {
         &mut self.head.extensions
     }
 
+    /// Attach `extension` to this request, replacing any existing value of the same type.
+    ///
+    /// See [`Request::extensions`] for more details.
     pub fn with_extension<E: Send + Sync + 'static>(mut self, extension: E) -> Self {
         self.extensions_mut().insert(extension);
         self
@@ -120,6 +141,13 @@ impl<T> Request<T> {
         self.extensions().get::<PeerId>()
     }
 
+    /// Returns the remote address of the peer who sent this request.
+    ///
+    /// This is only set on inbound requests.
+    pub fn remote_address(&self) -> Option<&std::net::SocketAddr> {
+        self.extensions().get::<std::net::SocketAddr>()
+    }
+
     pub fn body(&self) -> &T {
         &self.body
     }
@@ -233,12 +261,102 @@ impl<T> Request<T> {
             .ok()
             .flatten()
     }
+
+    /// Returns the point in time by which the sender of an inbound request expects a response,
+    /// or None if the request wasn't sent with a timeout.
+    ///
+    /// The deadline is derived from the request's [`TIMEOUT`](super::header::TIMEOUT) header when
+    /// the request is received and is made available via the [`Deadline`] extension. Once it has
+    /// passed the sender will have given up on the request, so handlers performing expensive work
+    /// can use it to abort early.
+    pub fn deadline(&self) -> Option<std::time::Instant> {
+        self.extensions()
+            .get::<Deadline>()
+            .map(|deadline| deadline.0)
+    }
+
+    /// Mark this request as idempotent, meaning that it is safe for it to be handled more than
+    /// once. Only idempotent requests can be sent with
+    /// [`Network::rpc_hedged`](crate::Network::rpc_hedged).
+    ///
+    /// The flag is carried in the [`IDEMPOTENT`](super::header::IDEMPOTENT) header.
+    pub fn set_idempotent(&mut self) {
+        self.headers_mut()
+            .insert(super::header::IDEMPOTENT.into(), "true".into());
+    }
+
+    /// Mark this request as idempotent.
+    ///
+    /// See [`Request::set_idempotent`] for more details.
+    pub fn with_idempotent(mut self) -> Self {
+        self.set_idempotent();
+        self
+    }
+
+    /// Returns whether this request has been marked as idempotent.
+    pub fn is_idempotent(&self) -> bool {
+        self.headers()
+            .get(super::header::IDEMPOTENT)
+            .map(|value| value == "true")
+            .unwrap_or(false)
+    }
+
+    /// Set the priority of this request relative to other requests sent to the same peer.
+    ///
+    /// Requests with a higher priority have their data sent ahead of requests with a lower
+    /// priority which are in flight on the same connection at the same time, which can be used to
+    /// keep latency-sensitive RPCs from being delayed behind bulk transfers. Requests default to a
+    /// priority of `0`.
+    ///
+    /// This is a best-effort hint which only affects how this side schedules sending the request.
+    /// It isn't sent to the peer and has no effect on how the peer handles the request or sends
+    /// its response.
+    pub fn set_priority(&mut self, priority: i32) {
+        self.extensions_mut().insert(Priority(priority));
+    }
+
+    /// Set the priority of this request.
+    ///
+    /// See [`Request::set_priority`] for more details.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.set_priority(priority);
+        self
+    }
+
+    /// Returns the priority of this request, which is `0` unless set via
+    /// [`Request::set_priority`].
+    pub fn priority(&self) -> i32 {
+        self.extensions()
+            .get::<Priority>()
+            .map(|priority| priority.0)
+            .unwrap_or_default()
+    }
 }
 
+/// The priority hint set via [`Request::set_priority`], kept in the request's extensions.
+#[derive(Clone, Copy, Debug)]
+struct Priority(i32);
+
 impl Request<bytes::Bytes> {
     pub fn empty() -> Self {
         Self::new(bytes::Bytes::new())
     }
+
+    /// Make a copy of this request's route, headers, body and priority. Other extensions are not
+    /// copied.
+    pub(crate) fn duplicate(&self) -> Self {
+        let head = RequestHeader {
+            route: self.head.route.clone(),
+            version: self.head.version,
+            headers: self.head.headers.clone(),
+            extensions: Default::default(),
+        };
+        let mut request = Self::from_parts(head, self.body.clone());
+        if let Some(priority) = self.extensions().get::<Priority>() {
+            request.extensions_mut().insert(*priority);
+        }
+        request
+    }
 }
 
 pub trait IntoRequest<T> {
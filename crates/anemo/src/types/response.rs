@@ -48,10 +48,11 @@ impl RawResponseHeader {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u16)]
 #[non_exhaustive]
 pub enum StatusCode {
+    #[default]
     Success = 200,
     BadRequest = 400,
     NotFound = 404,
@@ -105,12 +106,6 @@ impl StatusCode {
     }
 }
 
-impl Default for StatusCode {
-    fn default() -> Self {
-        Self::Success
-    }
-}
-
 impl std::fmt::Display for StatusCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use StatusCode::*;
@@ -129,6 +124,19 @@ impl std::fmt::Display for StatusCode {
     }
 }
 
+/// A QUIC application error code with which an RPC's response stream is reset, instead of the
+/// response being sent.
+///
+/// When a handler inserts this into the extensions of the [`Response`] it returns, the caller's
+/// [`Peer::rpc`](crate::Peer::rpc) fails with
+/// [`RpcError::Application`](crate::RpcError::Application) carrying the same `code`. This has no
+/// effect on responses to messages, which are discarded.
+///
+/// The code must be nonzero, as `0` is used when a stream is reset for any other reason, and must
+/// fit in a QUIC varint (less than 2^62).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApplicationErrorCode(pub u64);
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct InvalidStatusCodeError(u16);
 
@@ -147,6 +155,13 @@ pub struct Response<T> {
 }
 
 impl<T> Response<T> {
+    /// Create a new response with the given `body`.
+    ///
+    /// A `Bytes` body is written to the stream without being copied, so responses can be served
+    /// straight out of a larger shared buffer by slicing it with [`Bytes::slice`]. The slice holds
+    /// a reference to the shared buffer, which is kept alive until the response has been sent.
+    ///
+    /// [`Bytes::slice`]: bytes::Bytes::slice
     pub fn new(body: T) -> Response<T> {
         Self::from_parts(ResponseHeader::default(), body)
     }
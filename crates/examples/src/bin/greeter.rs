@@ -30,8 +30,8 @@ async fn main() {
         let peer_id = {
             if peers.is_empty() {
                 match receiver.recv().await.unwrap() {
-                    PeerEvent::NewPeer(peer_id) => peer_id,
-                    PeerEvent::LostPeer(_, _) => todo!(),
+                    PeerEvent::NewPeer(peer_id, _) => peer_id,
+                    PeerEvent::LostPeer(..) => todo!(),
                 }
             } else {
                 peers.pop().unwrap()